@@ -0,0 +1,136 @@
+use std::{
+    panic::{AssertUnwindSafe, catch_unwind, resume_unwind},
+    path::Path,
+    sync::mpsc,
+    thread,
+};
+
+use rusqlite::Connection;
+use tokio::sync::oneshot;
+
+use crate::{CommonTableExpression, DbTable, Result};
+
+type Job = Box<dyn FnOnce(&mut Connection) + Send>;
+
+/// An async handle to a connection living on its own thread, so queries never block the
+/// executor. Clones share the connection and its thread, which stops once every clone is dropped.
+///
+/// Builders get `build_async`, `build_raw_async` and `build_val_async` taking this handle.
+#[derive(Clone)]
+pub struct AsyncConnection {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl AsyncConnection {
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        Self::open_with(move || Connection::open(path)).await
+    }
+
+    pub async fn open_in_memory() -> Result<Self> {
+        Self::open_with(Connection::open_in_memory).await
+    }
+
+    /// Moves an already configured connection to its own thread.
+    pub fn from_connection(conn: Connection) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            let mut conn = conn;
+            for job in receiver {
+                job(&mut conn);
+            }
+        });
+        Self { jobs }
+    }
+
+    async fn open_with(
+        open: impl FnOnce() -> rusqlite::Result<Connection> + Send + 'static,
+    ) -> Result<Self> {
+        let (sender, receiver) = oneshot::channel();
+        thread::spawn(move || {
+            let _ = sender.send(open().map(Self::from_connection).map_err(Into::into));
+        });
+        receiver.await.expect("opening thread panicked")
+    }
+
+    /// Runs `f` on the connection's thread. Panics in `f` are resumed in the caller.
+    pub async fn call<R, E, F>(&self, f: F) -> std::result::Result<R, E>
+    where
+        R: Send + 'static,
+        E: Send + 'static,
+        F: FnOnce(&mut Connection) -> std::result::Result<R, E> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        let job: Job = Box::new(move |conn| {
+            let _ = sender.send(catch_unwind(AssertUnwindSafe(|| f(conn))));
+        });
+        self.jobs
+            .send(job)
+            .expect("the connection thread only stops once every handle is dropped");
+        match receiver.await.expect("jobs always send their result") {
+            Ok(result) => result,
+            Err(panic) => resume_unwind(panic),
+        }
+    }
+
+    /// Async [`DbTable::create_table`].
+    pub async fn create_table<T: DbTable>(&self) -> Result<usize>
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        self.call(|conn| T::create_table(conn)).await
+    }
+
+    /// Async [`DbTable::select`].
+    pub async fn select<T>(
+        &self,
+        where_clause: impl Into<String>,
+        params: impl rusqlite::Params + Send + 'static,
+    ) -> Result<Box<[T]>>
+    where
+        T: DbTable + Send + 'static,
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        let where_clause = where_clause.into();
+        self.call(move |conn| T::select(conn, &where_clause, params))
+            .await
+    }
+
+    /// Async [`DbTable::select_one`].
+    pub async fn select_one<T>(
+        &self,
+        where_clause: impl Into<String>,
+        params: impl rusqlite::Params + Send + 'static,
+    ) -> Result<Option<T>>
+    where
+        T: DbTable + Send + 'static,
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        let where_clause = where_clause.into();
+        self.call(move |conn| T::select_one(conn, &where_clause, params))
+            .await
+    }
+
+    /// Async [`DbTable::delete`].
+    pub async fn delete<T: DbTable>(
+        &self,
+        where_clause: impl Into<String>,
+        params: impl rusqlite::Params + Send + 'static,
+    ) -> Result<usize>
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        let where_clause = where_clause.into();
+        self.call(move |conn| T::delete(conn, &where_clause, params))
+            .await
+    }
+
+    /// Async [`CommonTableExpression::select`].
+    pub async fn select_cte<C>(&self, params: C::Params) -> Result<Box<[C]>>
+    where
+        C: CommonTableExpression + Send + 'static,
+        C::Params: Send + 'static,
+    {
+        self.call(move |conn| C::select(conn, params)).await
+    }
+}
@@ -20,9 +20,11 @@ fn create_tables(c: &mut Criterion) {
     const DB_NAME: &str = ":memory:";
     let db_path = std::path::PathBuf::from(DB_NAME);
 
-    let conn = rusqlite::Connection::open(&db_path).unwrap();
-    let _ = conn.execute("PRAGMA journal_mode = WAL;", []);
-    UsersTable::create_table(&conn).unwrap();
+    let conn = Database::open(&db_path)
+        .wal()
+        .table::<UsersTable>()
+        .connect()
+        .unwrap();
 
     let a = UsersTable::new()
         .with_name("Alice")
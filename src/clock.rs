@@ -0,0 +1,122 @@
+//! The time `#[created_at]` and `#[updated_at]` fields are set to, replaceable per thread so
+//! tests can control it.
+//!
+//! ```ignore
+//! let clock = MockClock::new(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+//! let _guard = clock::set_clock(clock.clone());
+//! let mut user = User::new().with_name("Alice").build_val(&conn)?;
+//! clock.advance(TimeDelta::hours(1));
+//! user.update(&conn)?;
+//! ```
+
+use std::{cell::RefCell, rc::Rc};
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
+
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system time, used unless a thread sets another clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the time.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Rc<RefCell<DateTime<Utc>>>,
+}
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Rc::new(RefCell::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.borrow_mut() = now;
+    }
+
+    pub fn advance(&self, delta: TimeDelta) {
+        *self.now.borrow_mut() += delta;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+}
+
+thread_local! {
+    static CLOCK: RefCell<Option<Rc<dyn Clock>>> = const { RefCell::new(None) };
+}
+
+/// The time of the calling thread's clock.
+pub fn now() -> DateTime<Utc> {
+    CLOCK
+        .with_borrow(|clock| clock.clone())
+        .map_or_else(Utc::now, |clock| clock.now())
+}
+
+/// Sets the calling thread's clock until the guard is dropped. Connections running on their own
+/// thread, like `AsyncConnection`, keep using theirs.
+#[must_use = "the clock is reset when the guard is dropped"]
+pub fn set_clock(clock: impl Clock + 'static) -> ClockGuard {
+    let previous = CLOCK.with_borrow_mut(|current| current.replace(Rc::new(clock)));
+    ClockGuard { previous }
+}
+
+/// Restores the previous clock of the thread on drop.
+pub struct ClockGuard {
+    previous: Option<Rc<dyn Clock>>,
+}
+
+impl Drop for ClockGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CLOCK.with_borrow_mut(|current| *current = previous);
+    }
+}
+
+/// Types of `#[created_at]` and `#[updated_at]` fields.
+pub trait Timestamp: Sized {
+    /// SQL for the current time in the format the type is stored in, used by the
+    /// `#[updated_at(trigger)]` trigger.
+    const SQL_NOW: &'static str;
+    fn from_utc(now: DateTime<Utc>) -> Self;
+
+    /// The time of the thread's clock, see [`set_clock`].
+    fn now() -> Self {
+        Self::from_utc(now())
+    }
+}
+
+impl Timestamp for DateTime<Utc> {
+    const SQL_NOW: &'static str = "strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')";
+    fn from_utc(now: DateTime<Utc>) -> Self {
+        now
+    }
+}
+
+impl Timestamp for NaiveDateTime {
+    const SQL_NOW: &'static str = "strftime('%Y-%m-%d %H:%M:%f', 'now')";
+    fn from_utc(now: DateTime<Utc>) -> Self {
+        now.naive_utc()
+    }
+}
+
+/// Seconds since the Unix epoch.
+impl Timestamp for i64 {
+    const SQL_NOW: &'static str = "unixepoch()";
+    fn from_utc(now: DateTime<Utc>) -> Self {
+        now.timestamp()
+    }
+}
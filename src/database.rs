@@ -0,0 +1,163 @@
+use std::{fmt::Display, path::PathBuf, time::Duration};
+
+use rusqlite::Connection;
+
+use crate::{DbFtsTable, DbTable, Result, Schema};
+
+/// `PRAGMA synchronous`, how often SQLite waits for writes to reach the disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    Off,
+    /// Safe from corruption in WAL mode, but a power loss may roll back the last commits.
+    Normal,
+    Full,
+    Extra,
+}
+
+impl Display for Synchronous {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        };
+        write!(f, "{s}")
+    }
+}
+
+/// Settings for opening a connection, applied before the registered tables are created.
+///
+/// ```ignore
+/// let conn = Database::open("app.db")
+///     .wal()
+///     .synchronous(Synchronous::Normal)
+///     .table::<UserRole>()
+///     .table::<User>()
+///     .connect()?;
+/// ```
+pub struct Database {
+    path: Option<PathBuf>,
+    wal: bool,
+    foreign_keys: bool,
+    busy_timeout: Option<Duration>,
+    pragmas: Vec<(String, String)>,
+    schema: Schema,
+}
+
+impl Database {
+    pub fn open(path: impl Into<PathBuf>) -> Self {
+        Self::new(Some(path.into()))
+    }
+
+    pub fn open_in_memory() -> Self {
+        Self::new(None)
+    }
+
+    fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            path,
+            wal: false,
+            foreign_keys: true,
+            busy_timeout: None,
+            pragmas: Vec::new(),
+            schema: Schema::new(),
+        }
+    }
+
+    /// `PRAGMA journal_mode = WAL`. In-memory databases ignore it.
+    pub fn wal(mut self) -> Self {
+        self.wal = true;
+        self
+    }
+
+    /// `PRAGMA foreign_keys`, which SQLite turns off by default. On unless disabled here, so
+    /// `#[foreign_key]` constraints are enforced.
+    pub fn foreign_keys(mut self, foreign_keys: bool) -> Self {
+        self.foreign_keys = foreign_keys;
+        self
+    }
+
+    /// How long to retry statements while another connection holds a lock.
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.busy_timeout = Some(timeout);
+        self
+    }
+
+    pub fn synchronous(self, synchronous: Synchronous) -> Self {
+        self.pragma("synchronous", synchronous)
+    }
+
+    /// `PRAGMA cache_size`, in pages when positive and in KiB when negative.
+    pub fn cache_size(self, cache_size: i64) -> Self {
+        self.pragma("cache_size", cache_size)
+    }
+
+    fn pragma(mut self, name: &str, value: impl Display) -> Self {
+        self.pragmas.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Creates the table on [`Database::connect`], after the registered tables it has foreign
+    /// keys to.
+    pub fn table<T: DbTable>(mut self) -> Self
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        self.schema = self.schema.table::<T>();
+        self
+    }
+
+    /// Creates the FTS table on [`Database::connect`], after its content table.
+    pub fn fts_table<F: DbFtsTable>(mut self) -> Self
+    where
+        rusqlite::Error: for<'a> From<<F::Content as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        self.schema = self.schema.fts_table::<F>();
+        self
+    }
+
+    /// Registers every table of the schema, see [`Database::table`].
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = self.schema.merge(schema);
+        self
+    }
+
+    pub fn connect(self) -> Result<Connection> {
+        let conn = match &self.path {
+            Some(path) => Connection::open(path)?,
+            None => Connection::open_in_memory()?,
+        };
+        configure(
+            &conn,
+            self.busy_timeout,
+            self.wal,
+            self.foreign_keys,
+            &self.pragmas,
+        )?;
+        self.schema.create_all(&conn)?;
+        Ok(conn)
+    }
+}
+
+/// The settings [`Database::connect`] and the connection pool apply to each connection, in order.
+/// The journal mode is stored in the file, so only connections that write need to set WAL.
+pub(crate) fn configure(
+    conn: &Connection,
+    busy_timeout: Option<Duration>,
+    wal: bool,
+    foreign_keys: bool,
+    pragmas: &[(String, String)],
+) -> rusqlite::Result<()> {
+    if let Some(timeout) = busy_timeout {
+        conn.busy_timeout(timeout)?;
+    }
+    if wal {
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
+    }
+    conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+    for (name, value) in pragmas {
+        conn.execute_batch(&format!("PRAGMA {name} = {value};"))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use std::fmt::Display;
+
+use rusqlite::ffi;
+
+use crate::{DbTable, ValidationErrors};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The error of every query the crate runs, with constraint failures of inserts, updates and
+/// deletes mapped to the table type and field names. Tables are named after their Rust type and
+/// columns after their fields.
+///
+/// ```ignore
+/// match User::new().with_email(email).build(&conn) {
+///     Err(err) if err.is_unique::<User>(&["email"]) => "email already taken",
+///     ...
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A `UNIQUE` constraint or unique index over `fields`.
+    Unique {
+        table: String,
+        fields: Vec<String>,
+    },
+    PrimaryKey {
+        table: String,
+        fields: Vec<String>,
+    },
+    NotNull {
+        table: String,
+        field: String,
+    },
+    /// SQLite doesn't say which foreign key failed, so `fields` are only candidates: every
+    /// `#[foreign_key]` field of the table when it's known. A failing delete comes from a table
+    /// referencing this one instead, and none of them failed.
+    ForeignKey {
+        table: Option<String>,
+        fields: Vec<String>,
+    },
+    /// A `CHECK` constraint, `constraint` is its expression or name. SQLite doesn't report the
+    /// columns, so `fields` are a best guess when the table is known: the fields whose names
+    /// appear as words in `constraint`, none for a named constraint.
+    Check {
+        table: Option<String>,
+        fields: Vec<String>,
+        constraint: String,
+    },
+    /// `#[validate(...)]` rules broken before the statement ran.
+    Validation(ValidationErrors),
+    /// An update of a row whose `#[version]` changed since it was read, or which no longer
+    /// exists.
+    StaleVersion {
+        table: String,
+    },
+    Sqlite(rusqlite::Error),
+}
+
+/// The parts of a table's metadata the constraint messages are mapped with.
+struct TableMeta {
+    name: &'static str,
+    columns: Box<[&'static str]>,
+    foreign_key_fields: &'static [&'static str],
+}
+
+impl Error {
+    /// Classifies an error of a statement on `T`, filling in the fields SQLite doesn't report.
+    pub fn for_table<T: DbTable>(err: rusqlite::Error) -> Self
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        Self::classify(
+            err,
+            Some(TableMeta {
+                name: T::TABLE_NAME,
+                columns: T::column_names(),
+                foreign_key_fields: T::FOREIGN_KEY_FIELDS,
+            }),
+        )
+    }
+
+    fn classify(err: rusqlite::Error, table: Option<TableMeta>) -> Self {
+        let (code, message) = match &err {
+            rusqlite::Error::SqliteFailure(code, Some(message))
+                if code.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                (code.extended_code, message.as_str())
+            }
+            _ => return Error::Sqlite(err),
+        };
+        // Messages look like `UNIQUE constraint failed: User.email`
+        let detail = message.split_once(": ").map_or("", |(_, detail)| detail);
+        let columns = || {
+            let mut table = String::new();
+            let fields = detail
+                .split(", ")
+                .filter_map(|column| column.split_once('.'))
+                .map(|(t, field)| {
+                    table = t.to_string();
+                    field.to_string()
+                })
+                .collect::<Vec<_>>();
+            (table, fields)
+        };
+
+        match code {
+            ffi::SQLITE_CONSTRAINT_UNIQUE => {
+                let (table, fields) = columns();
+                Error::Unique { table, fields }
+            }
+            ffi::SQLITE_CONSTRAINT_PRIMARYKEY => {
+                let (table, fields) = columns();
+                Error::PrimaryKey { table, fields }
+            }
+            ffi::SQLITE_CONSTRAINT_NOTNULL => {
+                let (table, mut fields) = columns();
+                Error::NotNull {
+                    table,
+                    field: fields.pop().unwrap_or_default(),
+                }
+            }
+            ffi::SQLITE_CONSTRAINT_FOREIGNKEY => Error::ForeignKey {
+                table: table.as_ref().map(|t| t.name.to_string()),
+                fields: table
+                    .map(|t| t.foreign_key_fields.iter().map(|f| f.to_string()).collect())
+                    .unwrap_or_default(),
+            },
+            ffi::SQLITE_CONSTRAINT_CHECK => {
+                let fields = table
+                    .as_ref()
+                    .map(|t| {
+                        t.columns
+                            .iter()
+                            .filter(|column| {
+                                detail
+                                    .split(|c: char| !c.is_alphanumeric() && c != '_')
+                                    .any(|word| word == **column)
+                            })
+                            .map(|column| column.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Error::Check {
+                    table: table.map(|t| t.name.to_string()),
+                    fields,
+                    constraint: detail.to_string(),
+                }
+            }
+            _ => Error::Sqlite(err),
+        }
+    }
+
+    /// The table of a constraint failure, if known.
+    pub fn table(&self) -> Option<&str> {
+        match self {
+            Error::Unique { table, .. }
+            | Error::PrimaryKey { table, .. }
+            | Error::NotNull { table, .. }
+            | Error::StaleVersion { table } => Some(table),
+            Error::ForeignKey { table, .. } | Error::Check { table, .. } => table.as_deref(),
+            Error::Validation(errors) => Some(errors.table),
+            Error::Sqlite(_) => None,
+        }
+    }
+
+    /// The fields of a constraint failure, only candidates for [`Error::ForeignKey`] and
+    /// [`Error::Check`].
+    pub fn fields(&self) -> &[String] {
+        match self {
+            Error::Unique { fields, .. }
+            | Error::PrimaryKey { fields, .. }
+            | Error::ForeignKey { fields, .. }
+            | Error::Check { fields, .. } => fields,
+            Error::NotNull { field, .. } => std::slice::from_ref(field),
+            Error::Validation(_) | Error::StaleVersion { .. } | Error::Sqlite(_) => &[],
+        }
+    }
+
+    /// Whether a `UNIQUE` constraint over exactly `fields` of `T` failed.
+    pub fn is_unique<T: DbTable>(&self, fields: &[&str]) -> bool
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        matches!(self, Error::Unique { .. })
+            && self.table() == Some(T::TABLE_NAME)
+            && self.fields() == fields
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Unique { table, fields } => {
+                write!(f, "{table} with this {} already exists", fields.join(", "))
+            }
+            Error::PrimaryKey { table, fields } => {
+                write!(f, "{table} with this {} already exists", fields.join(", "))
+            }
+            Error::NotNull { table, field } => write!(f, "{table}.{field} can't be NULL"),
+            Error::ForeignKey {
+                table: Some(table), ..
+            } => write!(f, "foreign key of {table} failed"),
+            Error::ForeignKey { table: None, .. } => write!(f, "foreign key constraint failed"),
+            Error::Check {
+                table: Some(table),
+                constraint,
+                ..
+            } => write!(f, "check of {table} failed: {constraint}"),
+            Error::Check {
+                table: None,
+                constraint,
+                ..
+            } => write!(f, "check failed: {constraint}"),
+            Error::Validation(errors) => errors.fmt(f),
+            Error::StaleVersion { table } => {
+                write!(f, "{table} was changed since it was read")
+            }
+            Error::Sqlite(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Validation(errors) => Some(errors),
+            Error::Sqlite(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Classifies constraint failures without knowing the table, see [`Error::for_table`].
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::classify(err, None)
+    }
+}
+
+impl From<ValidationErrors> for Error {
+    fn from(errors: ValidationErrors) -> Self {
+        Error::Validation(errors)
+    }
+}
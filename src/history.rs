@@ -0,0 +1,205 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{
+    OptionalExtension, ToSql,
+    types::{FromSql, FromSqlError, FromSqlResult, ValueRef},
+};
+
+use crate::{DbTable, Result, Timestamp};
+
+/// The statement a history entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryOperation {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Display for HistoryOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            HistoryOperation::Insert => "INSERT",
+            HistoryOperation::Update => "UPDATE",
+            HistoryOperation::Delete => "DELETE",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromSql for HistoryOperation {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "INSERT" => Ok(HistoryOperation::Insert),
+            "UPDATE" => Ok(HistoryOperation::Update),
+            "DELETE" => Ok(HistoryOperation::Delete),
+            other => Err(FromSqlError::other(UnknownOperation(other.to_string()))),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UnknownOperation(String);
+
+impl Display for UnknownOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown history operation `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownOperation {}
+
+/// A version of a row: the one an insert stored, or the one an update or delete replaced.
+#[derive(Debug, Clone)]
+pub struct History<T> {
+    /// The inserted row, or the row as it was before the update or delete.
+    pub row: T,
+    pub operation: HistoryOperation,
+    /// When the statement ran, in the database's time.
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Tables deriving `DbTable` with `#[history]`. Triggers copy a row into the `<Table>History`
+/// table every time it's inserted, updated or deleted, along with the operation and the time.
+///
+/// The times are the database's, not the [`clock`](crate::clock) of the thread, so a mocked clock
+/// doesn't move them.
+///
+/// The history table is created along with the table and its columns have no type, so the old
+/// values are kept exactly as they were stored. Dropping the table keeps its history.
+pub trait DbHistory: DbTable
+where
+    rusqlite::Error: for<'a> From<<Self as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+{
+    /// The primary key, or a tuple of the `#[composite_key]` fields.
+    type Key;
+    const KEY_COLUMNS: &'static [&'static str];
+
+    fn key_params(key: &Self::Key) -> Vec<Box<dyn ToSql + '_>>;
+
+    fn history_table_name() -> String {
+        format!("{}History", Self::TABLE_NAME)
+    }
+
+    /// The history table, its key index and the triggers filling it, separated by `;`.
+    fn create_history_str() -> String {
+        let table = Self::TABLE_NAME;
+        let history = Self::history_table_name();
+        let columns = Self::column_names().join(", ");
+        let values = |row: &str| {
+            Self::column_names()
+                .iter()
+                .map(|c| format!("{row}.{c}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let now = <DateTime<Utc> as Timestamp>::SQL_NOW;
+        let mut statements = vec![
+            format!(
+                "CREATE TABLE IF NOT EXISTS {history} (
+    {columns},
+    history_operation TEXT NOT NULL,
+    history_changed_at TEXT NOT NULL,
+    history_id INTEGER PRIMARY KEY
+)"
+            ),
+            format!(
+                "CREATE INDEX IF NOT EXISTS {history}_key ON {history} ({})",
+                Self::KEY_COLUMNS.join(", ")
+            ),
+        ];
+        for (event, row) in [("INSERT", "NEW"), ("UPDATE", "OLD"), ("DELETE", "OLD")] {
+            statements.push(format!(
+                "CREATE TRIGGER IF NOT EXISTS {table}_history_{} AFTER {event} ON {table} \
+                FOR EACH ROW BEGIN \
+                INSERT INTO {history} ({columns}, history_operation, history_changed_at) \
+                VALUES ({}, '{event}', {now}); END",
+                event.to_lowercase(),
+                values(row),
+            ));
+        }
+        statements.join(";\n")
+    }
+
+    /// The recorded versions of the row with the key, oldest first.
+    fn history(conn: &rusqlite::Connection, key: Self::Key) -> Result<Box<[History<Self>]>> {
+        let sql = format!(
+            "SELECT {}, history_operation, history_changed_at FROM {} WHERE {} ORDER BY history_id",
+            Self::column_getters(),
+            Self::history_table_name(),
+            key_condition(Self::KEY_COLUMNS),
+        );
+        let params = Self::key_params(&key);
+        let params = params.iter().map(|p| p.as_ref()).collect::<Vec<_>>();
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params.as_slice(), |row| {
+                Ok(History {
+                    row: Self::try_from(row)?,
+                    operation: row.get(Self::column_count())?,
+                    changed_at: row.get(Self::column_count() + 1)?,
+                })
+            })?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows)
+    }
+
+    /// The row with the key as it was at `timestamp`, in the database's time: the version the
+    /// first change after it replaced, or the current row if it hasn't changed since. `None` if
+    /// the row was inserted after `timestamp` or deleted before it.
+    fn as_of(
+        conn: &rusqlite::Connection,
+        key: Self::Key,
+        timestamp: DateTime<Utc>,
+    ) -> Result<Option<Self>> {
+        let params = Self::key_params(&key);
+        let mut params = params.iter().map(|p| p.as_ref()).collect::<Vec<_>>();
+        let condition = key_condition(Self::KEY_COLUMNS);
+        // Compared in the format the triggers record the times in
+        let at = <DateTime<Utc> as Timestamp>::SQL_NOW.replacen(
+            "'now'",
+            &format!("?{}", params.len() + 1),
+            1,
+        );
+        let sql = format!(
+            "SELECT {}, history_operation FROM {} WHERE {condition} AND history_changed_at > {at} ORDER BY history_id LIMIT 1",
+            Self::column_getters(),
+            Self::history_table_name(),
+        );
+        params.push(&timestamp);
+        let next = conn
+            .query_row(&sql, params.as_slice(), |row| {
+                Ok((Self::try_from(row)?, row.get(Self::column_count())?))
+            })
+            .optional()?;
+        match next {
+            Some((_, HistoryOperation::Insert)) => return Ok(None),
+            Some((replaced, _)) => return Ok(Some(replaced)),
+            None => {}
+        }
+        params.pop();
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {condition}",
+            Self::column_getters(),
+            Self::TABLE_NAME,
+        );
+        let row = conn
+            .query_row(&sql, params.as_slice(), |row| Ok(Self::try_from(row)?))
+            .optional()?;
+        Ok(row)
+    }
+
+    #[doc(hidden)]
+    fn column_count() -> usize {
+        Self::column_names().len()
+    }
+}
+
+fn key_condition(columns: &[&str]) -> String {
+    columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| format!("{column} = ?{}", i + 1))
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
@@ -1,11 +1,54 @@
+// Generated code names the crate's items by their full path, which needs to resolve here too
+extern crate self as typed_db;
+
+#[cfg(feature = "async")]
+mod async_connection;
+pub mod clock;
+mod database;
+mod error;
+mod history;
+pub mod pool;
+mod query_plan;
+mod schema;
+mod subscription;
 mod traits;
 mod types;
+mod validation;
 
+#[cfg(feature = "async")]
+pub use async_connection::AsyncConnection;
+pub use clock::Timestamp;
+pub use database::{Database, Synchronous};
+pub use error::{Error, Result};
+pub use history::{DbHistory, History, HistoryOperation};
+pub use query_plan::{PlanStep, QueryPlan, QueryPlanNode};
+pub use schema::Schema;
+pub use subscription::{ChangeEvent, ChangeKind, SubscriptionId, Subscriptions};
 pub use traits::*;
-pub use typed_db_derive::DbTable;
+pub use typed_db_derive::{DbFtsTable, DbTable, DbView};
+pub use types::{
+    AsBlob, AsText, BlobEncode, CheckedValue, DefaultKind, FieldSet, FieldUnset, IntegerOverflow,
+    ResultKind,
+};
+pub use validation::{FieldError, LazyRegex, ValidateLength, ValidationErrors};
 
 pub mod prelude {
+    #[cfg(feature = "async")]
+    pub use crate::async_connection::AsyncConnection;
+    pub use crate::clock::Timestamp;
+    pub use crate::database::{Database, Synchronous};
+    pub use crate::error::Error as DbError;
+    pub use crate::history::{DbHistory, History, HistoryOperation};
+    pub use crate::query_plan::QueryPlan;
+    pub use crate::schema;
+    pub use crate::schema::Schema;
+    pub use crate::subscription::{ChangeEvent, ChangeKind, Subscriptions};
     pub use crate::traits::*;
+    pub use crate::types::{
+        AsBlob, AsText, CheckedValue, DefaultKind, FieldSet, FieldUnset, IntegerOverflow,
+        ResultKind,
+    };
+    pub use crate::validation::{FieldError, LazyRegex, ValidationErrors};
     pub use typed_db_derive::*;
 }
 
@@ -25,6 +68,7 @@ mod tests {
         pub id: Id,
         pub name: String,
         #[unique]
+        #[collate(NOCASE)]
         pub email: String,
         #[default(CURRENT_TIMESTAMP)]
         pub created_date: DateTime<Utc>,
@@ -53,8 +97,255 @@ mod tests {
         pub active_date: DateTime<Utc>,
     }
 
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Counter {
+        #[primary_key]
+        pub id: Id,
+        pub hits: u64,
+        #[store_as(text)]
+        pub hash: u64,
+        #[store_as(blob)]
+        pub big: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    #[table(strict)]
+    pub struct Setting {
+        #[primary_key]
+        pub id: Id,
+        pub enabled: bool,
+        pub weight: f64,
+        pub version: u64,
+        #[default(CURRENT_TIMESTAMP)]
+        pub updated: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    #[table(strict, without_rowid)]
+    pub struct Tag {
+        #[composite_key]
+        pub name: String,
+        #[composite_key]
+        pub scope: String,
+        pub uses: i64,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Invoice {
+        #[primary_key(autoincrement)]
+        pub id: i64,
+        pub quantity: i64,
+        pub unit_price: f64,
+        #[generated("quantity * unit_price", stored)]
+        pub total: f64,
+        #[generated("quantity > 10")]
+        pub bulk: bool,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Defaults {
+        #[primary_key]
+        pub id: Id,
+        #[default(-5)]
+        pub offset: i64,
+        #[default(-0.25)]
+        pub ratio: f64,
+        #[default("it's")]
+        pub label: String,
+        #[default(b"\x01\xff")]
+        pub raw: Vec<u8>,
+        #[default(NULL)]
+        pub note: Option<String>,
+        #[default(("strftime('%s','now')"))]
+        pub epoch: i64,
+        #[default(TRUE)]
+        pub active: bool,
+    }
+
+    fn new_token() -> String {
+        "generated-token".to_string()
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Session {
+        #[primary_key]
+        pub id: Id,
+        #[foreign_key(User::id)]
+        pub user_id: Id,
+        #[default_fn(new_token)]
+        pub token: String,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Country {
+        #[primary_key]
+        pub code: String,
+        pub name: String,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Department {
+        #[primary_key]
+        pub id: Id,
+        #[foreign_key(Employee::id)]
+        pub head: Option<Id>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Employee {
+        #[primary_key]
+        pub id: Id,
+        #[foreign_key(Department::id)]
+        pub department: Id,
+        #[foreign_key(Employee::id)]
+        pub mentor: Option<Id>,
+    }
+
+    fn without_spaces(handle: &str) -> Result<(), &'static str> {
+        match handle.contains(' ') {
+            true => Err("must not contain spaces"),
+            false => Ok(()),
+        }
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Member {
+        #[primary_key]
+        pub id: Id,
+        #[validate(length(min = 1, max = 16), with = without_spaces)]
+        pub handle: String,
+        #[validate(email)]
+        pub email: String,
+        #[validate(range(0..=120), check)]
+        pub age: u8,
+        #[validate(regex = r"^\+?[0-9 ]+$")]
+        pub phone: Option<String>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    #[hooks]
+    pub struct Account {
+        #[primary_key]
+        pub id: Id,
+        #[validate(email)]
+        pub email: String,
+        pub balance: i64,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Note {
+        #[primary_key]
+        pub id: Id,
+        pub body: String,
+        #[created_at]
+        pub created: DateTime<Utc>,
+        #[updated_at(trigger)]
+        pub updated: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Customer {
+        #[primary_key]
+        pub id: Id,
+        pub name: String,
+        #[soft_delete]
+        pub deleted_at: Option<DateTime<Utc>>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    pub struct Document {
+        #[primary_key]
+        pub id: Id,
+        pub title: String,
+        #[version]
+        pub version: i64,
+        #[updated_at]
+        pub edited: DateTime<Utc>,
+    }
+
+    #[derive(Debug, Clone, DbTable)]
+    #[history]
+    pub struct Article {
+        #[primary_key]
+        pub id: Id,
+        pub title: String,
+    }
+
+    #[derive(DbFtsTable)]
+    #[fts5(content = User, columns(name, email))]
+    pub struct UserSearch;
+
+    // Generated code only needs the crate root, not the prelude
+    mod without_prelude {
+        use crate::*;
+
+        #[derive(Debug, Clone, DbTable)]
+        #[hooks]
+        pub struct Note {
+            #[primary_key]
+            pub id: i32,
+            pub text: String,
+            #[version]
+            pub version: i64,
+        }
+
+        impl DbTableHooks for Note {
+            fn before_update(&mut self, _: &rusqlite::Connection) -> Result<()> {
+                self.text = self.text.trim().to_string();
+                Ok(())
+            }
+        }
+    }
+
+    thread_local! {
+        static ACCOUNT_EVENTS: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    fn account_event(event: String) {
+        ACCOUNT_EVENTS.with_borrow_mut(|events| events.push(event));
+    }
+
+    impl DbTableHooks for Account {
+        fn before_insert(
+            builder: &mut AccountBuilder<FieldSet, FieldSet>,
+            _: &rusqlite::Connection,
+        ) -> crate::Result<()> {
+            builder.email = builder.email.take().map(|e| e.trim().to_lowercase());
+            Ok(())
+        }
+
+        fn after_insert(&self, _: &rusqlite::Connection) -> crate::Result<()> {
+            account_event(format!("inserted {}", self.email));
+            Ok(())
+        }
+
+        fn before_update(&mut self, _: &rusqlite::Connection) -> crate::Result<()> {
+            self.email = self.email.trim().to_lowercase();
+            Ok(())
+        }
+
+        fn after_update(&self, _: &rusqlite::Connection) -> crate::Result<()> {
+            account_event(format!("updated {}", self.email));
+            Ok(())
+        }
+
+        fn before_delete(&self, _: &rusqlite::Connection) -> crate::Result<()> {
+            if self.balance != 0 {
+                let mut errors = ValidationErrors::new(Self::TABLE_NAME);
+                errors.push("balance", "delete", "must be zero");
+                return Err(errors.into());
+            }
+            Ok(())
+        }
+
+        fn after_delete(&self, _: &rusqlite::Connection) -> crate::Result<()> {
+            account_event(format!("deleted {}", self.email));
+            Ok(())
+        }
+    }
+
     #[derive(Debug, Clone, CommonTableExpression)]
-    #[cte_params("effective_time", "user_id")]
+    #[cte_params(effective_time: DateTime<Utc>, user_id: Id)]
     struct ActiveUser {
         #[param(User::id as "u", "u.id = params.user_id")]
         pub id: Id,
@@ -80,6 +371,91 @@ mod tests {
         pub team_leader: Option<Id>,
     }
 
+    #[derive(Debug, Clone, CommonTableExpression)]
+    #[cte_params("user_id")]
+    struct UserName {
+        #[param(User::name as "u", "u.id = params.user_id")]
+        pub name: String,
+    }
+
+    #[derive(Debug, Clone, CommonTableExpression)]
+    #[cte_params(user_id: Id)]
+    struct UserStats {
+        #[param(User::name as "u", "u.id = params.user_id")]
+        pub name: String,
+        #[aggregate(count, UserRole::role as "ur", "ur.user_id = params.user_id")]
+        pub role_count: i64,
+        #[aggregate(group_concat, UserRole::role as "ur", "ur.user_id = params.user_id")]
+        pub roles: Option<String>,
+        #[aggregate(max, UserRole::id as "ur", "ur.user_id = params.user_id")]
+        pub latest_role_id: Option<Id>,
+        #[exists(UserTeam as "ut", "ut.team_leader = params.user_id")]
+        pub is_leader: bool,
+        #[expr("params.user_id * 2")]
+        pub doubled_id: i64,
+        #[expr("params.user_id > 0")]
+        pub positive_id: bool,
+    }
+
+    #[derive(Debug, Clone, CommonTableExpression)]
+    #[cte_params(effective_time: DateTime<Utc>)]
+    #[cte_from(User as "u", where = "u.email LIKE '%@example.com' ORDER BY u.id")]
+    struct UserWithRole {
+        #[expr("u.id")]
+        pub id: Id,
+        #[expr("u.name")]
+        pub name: String,
+        #[param(
+            UserRole::role as "ur",
+            "ur.user_id = u.id
+             AND ur.active_date <= params.effective_time
+             ORDER BY ur.active_date DESC
+             LIMIT 1"
+        )]
+        pub role: Option<String>,
+    }
+
+    #[derive(Debug, Clone, CommonTableExpression)]
+    #[cte_params(user_id: Id)]
+    #[recursive_cte(
+        UserTeam as "ut",
+        parent = team_leader,
+        child = team_member,
+        start = "params.user_id"
+    )]
+    struct Report {
+        #[expr("tree.node")]
+        pub user_id: Id,
+        #[param(User::name as "u", "u.id = tree.node")]
+        pub name: String,
+        #[expr("tree.depth")]
+        pub depth: i64,
+    }
+
+    #[derive(Debug, Clone, CommonTableExpression)]
+    #[cte_params(user_id: Id)]
+    #[recursive_cte(
+        UserTeam as "ut",
+        parent = team_leader,
+        child = team_member,
+        start = "params.user_id",
+        direction = ancestors,
+        max_depth = 1
+    )]
+    struct DirectLeader {
+        #[expr("tree.node")]
+        pub user_id: Id,
+    }
+
+    #[derive(Debug, Clone, CommonTableExpression, DbView)]
+    #[cte_from(User as "u")]
+    struct TeamSize {
+        #[expr("u.id")]
+        pub leader: Id,
+        #[aggregate(count, UserTeam::team_member as "ut", "ut.team_leader = u.id")]
+        pub members: i64,
+    }
+
     #[test]
     fn t() -> Result<(), Box<dyn std::error::Error>> {
         let conn = rusqlite::Connection::open(":memory:")?;
@@ -106,7 +482,25 @@ mod tests {
             .with_team_leader(u2.id)
             .build(&conn)?;
 
-        let _a = ActiveUser::select(&conn, params![Utc::now(), u1.id])?;
+        let a = ActiveUser::select(
+            &conn,
+            ActiveUserParams {
+                effective_time: Utc::now(),
+                user_id: u1.id,
+            },
+        )?;
+        assert_eq!(a[0].name, "Bob");
+        assert_eq!(a[0].role.as_deref(), Some("Admin"));
+        assert_eq!(a[0].team_leader, Some(u2.id));
+        let positional = ActiveUser::select_raw(&conn, params![Utc::now(), u1.id])?;
+        assert_eq!(positional[0].email, "bob@example.com");
+        let untyped = UserName::select(
+            &conn,
+            UserNameParams {
+                user_id: u1.id.into(),
+            },
+        )?;
+        assert_eq!(untyped[0].name, "Bob");
 
         // println!("{}", User::create_table_str());
         // println!("{}", UserRole::create_table_str());
@@ -117,4 +511,1260 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn unsigned_storage() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        Counter::create_table(&conn)?;
+
+        let err = Counter::new()
+            .with_hits(u64::MAX)
+            .with_hash(0u64)
+            .build(&conn)
+            .unwrap_err();
+        let crate::Error::Sqlite(rusqlite::Error::ToSqlConversionFailure(err)) = err else {
+            panic!("expected a conversion failure, got {err:?}");
+        };
+        let overflow = err.downcast_ref::<IntegerOverflow>().unwrap();
+        assert_eq!((overflow.table, overflow.column), ("Counter", "hits"));
+
+        let c = Counter::new()
+            .with_hits(1u64)
+            .with_hash(u64::MAX)
+            .with_big(u64::MAX)
+            .build_val(&conn)?;
+        assert_eq!(c.hash, u64::MAX);
+        assert_eq!(c.big, Some(u64::MAX));
+
+        let negative = conn.execute("INSERT INTO Counter (hits, hash) VALUES (-1, '0')", []);
+        assert!(negative.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn strict_tables() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        Setting::create_table(&conn)?;
+        Tag::create_table(&conn)?;
+        assert!(Tag::create_table_str().ends_with(") STRICT, WITHOUT ROWID"));
+
+        let s = Setting::new()
+            .with_enabled(true)
+            .with_weight(0.5)
+            .with_version(3u64)
+            .build_val(&conn)?;
+        assert!(s.enabled);
+        let wrong_type = conn.execute(
+            "INSERT INTO Setting (enabled, weight, version) VALUES (1, 1.0, 'three')",
+            [],
+        );
+        assert!(wrong_type.is_err());
+
+        let key = Tag::new()
+            .with_name("rust")
+            .with_scope("lang")
+            .with_uses(1)
+            .build(&conn)?;
+        assert_eq!(key, ("rust".to_string(), "lang".to_string()));
+        let tag = Tag::new()
+            .with_name("sql")
+            .with_scope("lang")
+            .with_uses(2)
+            .build_val(&conn)?;
+        assert_eq!(tag.uses, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn column_constraints() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        Invoice::create_table(&conn)?;
+
+        User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build(&conn)?;
+        let duplicate = User::new()
+            .with_name("Bobby")
+            .with_email("BOB@example.com")
+            .build(&conn);
+        assert!(duplicate.is_err());
+
+        let first = Invoice::new()
+            .with_quantity(12)
+            .with_unit_price(0.5)
+            .build_val(&conn)?;
+        assert_eq!(first.total, 6.0);
+        assert!(first.bulk);
+        Invoice::delete(&conn, "WHERE id = ?1", [first.id])?;
+        let second = Invoice::new()
+            .with_quantity(1)
+            .with_unit_price(2.0)
+            .build_val(&conn)?;
+        assert!(second.id > first.id);
+        assert!(!second.bulk);
+
+        Ok(())
+    }
+
+    #[test]
+    fn default_values() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        Defaults::create_table(&conn)?;
+
+        let d = Defaults::new().with_active(false).build_val(&conn)?;
+        assert_eq!(d.offset, -5);
+        assert_eq!(d.ratio, -0.25);
+        assert_eq!(d.label, "it's");
+        assert_eq!(d.raw, vec![0x01, 0xff]);
+        assert_eq!(d.note, None);
+        assert!(d.epoch > 0);
+        assert!(!d.active);
+
+        Ok(())
+    }
+
+    #[test]
+    fn builder_defaults() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        Session::create_table(&conn)?;
+        Defaults::create_table(&conn)?;
+
+        let user = User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build_val(&conn)?;
+        let generated = Session::new().with_user_id(user.id).build_val(&conn)?;
+        assert_eq!(generated.token, "generated-token");
+        let explicit = Session::new()
+            .with_token("explicit")
+            .with_user_id(user.id)
+            .build_val(&conn)?;
+        assert_eq!(explicit.token, "explicit");
+
+        let all_defaults = Defaults::new().build_val(&conn)?;
+        assert!(all_defaults.active);
+
+        // Only INTEGER primary keys are assigned by SQLite, others must be set
+        Country::create_table(&conn)?;
+        let unset_code: CountryBuilder<FieldUnset, FieldSet> = Country::new().with_name("Norway");
+        let country = unset_code.with_code("NO").build_val(&conn)?;
+        assert_eq!(country.code, "NO");
+
+        Ok(())
+    }
+
+    #[test]
+    fn cte_computed_fields() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        UserRole::create_table(&conn)?;
+        UserTeam::create_table(&conn)?;
+
+        let bob = User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build_val(&conn)?;
+        let alice = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        UserRole::new()
+            .with_user_id(alice.id)
+            .with_role("Admin")
+            .build(&conn)?;
+        let last_role = UserRole::new()
+            .with_user_id(alice.id)
+            .with_role("Owner")
+            .build_val(&conn)?;
+        UserTeam::new()
+            .with_team_member(bob.id)
+            .with_team_leader(alice.id)
+            .build(&conn)?;
+
+        let stats = &UserStats::select(&conn, UserStatsParams { user_id: alice.id })?[0];
+        assert_eq!(stats.name, "Alice");
+        assert_eq!(stats.role_count, 2);
+        assert_eq!(stats.roles.as_deref(), Some("Admin,Owner"));
+        assert_eq!(stats.latest_role_id, Some(last_role.id));
+        assert!(stats.is_leader);
+        assert_eq!(stats.doubled_id, i64::from(alice.id) * 2);
+        assert!(stats.positive_id);
+
+        let stats = &UserStats::select(&conn, UserStatsParams { user_id: bob.id })?[0];
+        assert_eq!(stats.role_count, 0);
+        assert_eq!(stats.roles, None);
+        assert!(!stats.is_leader);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cte_from_base_table() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        UserRole::create_table(&conn)?;
+
+        let bob = User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build_val(&conn)?;
+        let alice = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        User::new()
+            .with_name("Eve")
+            .with_email("eve@elsewhere.org")
+            .build(&conn)?;
+        UserRole::new()
+            .with_user_id(alice.id)
+            .with_role("Admin")
+            .build(&conn)?;
+
+        let rows = UserWithRole::select(
+            &conn,
+            UserWithRoleParams {
+                effective_time: Utc::now(),
+            },
+        )?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!((rows[0].id, rows[0].role.as_deref()), (bob.id, None));
+        assert_eq!(
+            (rows[1].id, rows[1].role.as_deref()),
+            (alice.id, Some("Admin"))
+        );
+        assert_eq!(rows[1].name, "Alice");
+
+        Ok(())
+    }
+
+    #[test]
+    fn recursive_cte() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        UserTeam::create_table(&conn)?;
+
+        let ids = ["ceo", "manager", "engineer"]
+            .into_iter()
+            .map(|name| {
+                User::new()
+                    .with_name(name)
+                    .with_email(format!("{name}@example.com"))
+                    .build_val(&conn)
+                    .map(|u| u.id)
+            })
+            .collect::<crate::Result<Vec<_>>>()?;
+        for (leader, member) in [(0, 1), (1, 2), (2, 0)] {
+            UserTeam::new()
+                .with_team_leader(ids[leader])
+                .with_team_member(ids[member])
+                .build(&conn)?;
+        }
+
+        // The engineer -> ceo edge closes a cycle, which must not be followed back to the ceo
+        let reports = Report::select(&conn, ReportParams { user_id: ids[0] })?;
+        let reports = reports
+            .iter()
+            .map(|r| (r.user_id, r.name.as_str(), r.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(reports, [(ids[1], "manager", 1), (ids[2], "engineer", 2)]);
+
+        let leaders = DirectLeader::select(&conn, DirectLeaderParams { user_id: ids[2] })?;
+        assert_eq!(leaders.len(), 1);
+        assert_eq!(leaders[0].user_id, ids[1]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cte_view() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        UserTeam::create_table(&conn)?;
+        TeamSize::create_view(&conn)?;
+
+        let leader = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        for name in ["Bob", "Carol"] {
+            let member = User::new()
+                .with_name(name)
+                .with_email(format!("{name}@example.com"))
+                .build_val(&conn)?;
+            UserTeam::new()
+                .with_team_leader(leader.id)
+                .with_team_member(member.id)
+                .build(&conn)?;
+        }
+
+        let sizes = TeamSize::select_view(&conn, "WHERE members > 0", [])?;
+        assert_eq!(sizes.len(), 1);
+        assert_eq!((sizes[0].leader, sizes[0].members), (leader.id, 2));
+        let from_sql: i64 = conn.query_row(
+            "SELECT members FROM TeamSize WHERE leader = ?1",
+            [leader.id],
+            |row| row.get(0),
+        )?;
+        assert_eq!(from_sql, 2);
+
+        TeamSize::drop_view(&conn)?;
+        assert!(TeamSize::select_one_view(&conn, "", []).is_err());
+
+        // CTEs with parameters are stored for fixed values
+        let params = UserNameParams {
+            user_id: leader.id.into(),
+        };
+        UserName::create_view_as(&conn, "LeaderName", &params)?;
+        let names = UserName::select_view_as(&conn, "LeaderName", "", [])?;
+        assert_eq!(names.len(), 1);
+        assert_eq!(names[0].name, "Alice");
+
+        Ok(())
+    }
+
+    #[test]
+    fn query_plans() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = rusqlite::Connection::open(":memory:")?;
+        User::create_table(&conn)?;
+        UserRole::create_table(&conn)?;
+        UserTeam::create_table(&conn)?;
+
+        let by_email = User::explain_select(&conn, "WHERE email = ?1", ["bob@example.com"])?;
+        by_email.assert_no_full_scan();
+        assert!(matches!(
+            &by_email.nodes[0].step,
+            crate::PlanStep::Search { table, index: Some(_), automatic: false } if table == "User"
+        ));
+
+        let by_name = User::explain_delete(&conn, "WHERE name = ?1", ["Bob"])?;
+        assert_eq!(by_name.full_scans().len(), 1);
+        let panicked = std::panic::catch_unwind(|| by_name.assert_no_full_scan());
+        assert!(panicked.is_err());
+
+        let insert = User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .explain(&conn)?;
+        insert.assert_no_full_scan();
+
+        // UserRole.user_id has no index, so the CTE has to scan or build an automatic index
+        let cte = ActiveUser::explain(
+            &conn,
+            ActiveUserParams {
+                effective_time: Utc::now(),
+                user_id: 1,
+            },
+        )?;
+        assert!(!cte.full_scans().is_empty());
+        conn.execute("CREATE INDEX user_role_user ON UserRole (user_id)", [])?;
+        conn.execute(
+            "CREATE INDEX user_team_member ON UserTeam (team_member)",
+            [],
+        )?;
+        let cte = ActiveUser::explain(
+            &conn,
+            ActiveUserParams {
+                effective_time: Utc::now(),
+                user_id: 1,
+            },
+        )?;
+        cte.assert_no_full_scan();
+
+        // Only the CTE's own `params` table is exempt, not a table that happens to share its name
+        conn.execute("CREATE TABLE params (user_id INTEGER)", [])?;
+        let plan = crate::QueryPlan::explain(&conn, "SELECT * FROM params", [])?;
+        assert_eq!(plan.full_scans().len(), 1);
+
+        // SQLite before 3.36 reports tables as `TABLE x`
+        assert_eq!(
+            crate::PlanStep::parse("SEARCH TABLE User USING INDEX user_email (email=?)", &[]),
+            crate::PlanStep::Search {
+                table: "User".to_string(),
+                index: Some("user_email".to_string()),
+                automatic: false,
+            }
+        );
+        assert!(crate::PlanStep::parse("SCAN TABLE User", &[]).is_full_scan());
+
+        Ok(())
+    }
+
+    #[test]
+    fn connection_pool() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::pool::{Pool, PoolError};
+        use std::time::Duration;
+
+        let path = std::env::temp_dir().join(format!("typed_db_pool_{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let pool = Pool::builder(&path)
+            .readers(2)
+            .checkout_timeout(Duration::from_millis(100))
+            .health_check(true)
+            .pragma("cache_size", -2000)
+            .create_table::<User>()
+            .create_table::<UserRole>()
+            .build()?;
+
+        let journal_mode: String = pool
+            .reader()?
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        assert_eq!(journal_mode, "wal");
+        let foreign_keys: bool = pool
+            .reader()?
+            .query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        assert!(foreign_keys);
+
+        let writer = pool.writer()?;
+        let user = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&writer)?;
+        let orphan = UserRole::new()
+            .with_user_id(user.id + 1)
+            .with_role("admin")
+            .with_active_date(Utc::now())
+            .build(&writer);
+        assert!(orphan.is_err());
+        drop(writer);
+
+        let threads = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                std::thread::spawn(move || -> Result<usize, PoolError> {
+                    let conn = pool.reader()?;
+                    Ok(User::select(&conn, "", [])?.len())
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            assert_eq!(thread.join().unwrap()?, 1);
+        }
+
+        // Readers can't write, and there is only one writer
+        let reader = pool.reader()?;
+        assert!(User::delete(&reader, "", []).is_err());
+        drop(reader);
+        let writer = pool.writer()?;
+        assert!(matches!(pool.writer(), Err(PoolError::Timeout)));
+        drop(writer);
+        assert_eq!(pool.idle(), (1, 2));
+
+        // Open transactions are rolled back when the connection goes back to the pool
+        {
+            let mut writer = pool.writer()?;
+            let tx = writer.transaction()?;
+            User::delete(&tx, "", [])?;
+            std::mem::forget(tx);
+        }
+        let reader = pool.reader()?;
+        assert_eq!(User::select(&reader, "", [])?.len(), 1);
+        drop(reader);
+
+        drop(pool);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+
+        // The writer and the readers open a `file:` URI as the same database
+        let uri = format!(
+            "file:typed_db_pool_{}?mode=memory&cache=shared",
+            std::process::id()
+        );
+        let pool = Pool::builder(&uri)
+            .readers(1)
+            .create_table::<User>()
+            .build()?;
+        User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build(&*pool.writer()?)?;
+        assert_eq!(User::select(&*pool.reader()?, "", [])?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn database_builder() -> Result<(), Box<dyn std::error::Error>> {
+        use std::time::Duration;
+
+        assert_eq!(UserTeam::FOREIGN_TABLES, ["User"]);
+        assert!(User::FOREIGN_TABLES.is_empty());
+
+        let conn = Database::open_in_memory()
+            .busy_timeout(Duration::from_secs(1))
+            .synchronous(Synchronous::Normal)
+            .cache_size(-4000)
+            .table::<UserTeam>()
+            .table::<UserRole>()
+            .table::<User>()
+            .connect()?;
+
+        let tables = conn
+            .prepare("SELECT name FROM sqlite_schema WHERE type = 'table' ORDER BY rowid")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        assert_eq!(tables, ["User", "UserTeam", "UserRole"]);
+
+        let pragma = |name: &str| -> rusqlite::Result<i64> {
+            conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))
+        };
+        assert_eq!(pragma("foreign_keys")?, 1);
+        assert_eq!(pragma("synchronous")?, 1);
+        assert_eq!(pragma("cache_size")?, -4000);
+        assert_eq!(pragma("busy_timeout")?, 1000);
+
+        let orphan = UserRole::new()
+            .with_user_id(1)
+            .with_role("admin")
+            .with_active_date(Utc::now())
+            .build(&conn);
+        assert!(orphan.is_err());
+
+        let path = std::env::temp_dir().join(format!("typed_db_wal_{}.db", std::process::id()));
+        let conn = Database::open(&path).wal().foreign_keys(false).connect()?;
+        let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        assert_eq!(journal_mode, "wal");
+        let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        assert!(!foreign_keys);
+        drop(conn);
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn schema_registry() -> Result<(), Box<dyn std::error::Error>> {
+        let schema = schema![UserTeam, UserRole, User, Session];
+        assert_eq!(
+            schema.table_names(),
+            ["User", "UserTeam", "UserRole", "Session"]
+        );
+        let ddl = schema.full_ddl();
+        assert!(ddl.find("TABLE IF NOT EXISTS User ").unwrap() < ddl.find("UserTeam").unwrap());
+        assert_eq!(ddl.matches(";\n").count(), 4);
+
+        let conn = Database::open_in_memory()
+            .schema(schema.clone())
+            .connect()?;
+        let user = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        Session::new().with_user_id(user.id).build(&conn)?;
+        // Dropping User first would fail with foreign keys on
+        schema.drop_all(&conn)?;
+        let tables: i64 = conn.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE type = 'table'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(tables, 0);
+
+        conn.execute_batch(&schema.full_ddl())?;
+        schema.drop_all(&conn)?;
+
+        // Self references are fine, and tables referencing each other keep their registration
+        // order
+        assert_eq!(schema![Employee].table_names(), ["Employee"]);
+        let cyclic = schema![User, Employee, Department];
+        assert_eq!(cyclic.table_names(), ["User", "Employee", "Department"]);
+        assert_eq!(cyclic.cycles(), [["Employee", "Department"]]);
+        assert!(schema.cycles().is_empty() && schema![Employee].cycles().is_empty());
+        cyclic.create_all(&conn)?;
+        let department = Department::new().build_val(&conn)?;
+        let head = Employee::new()
+            .with_department(department.id)
+            .build_val(&conn)?;
+        conn.execute(
+            "UPDATE Department SET head = ?1 WHERE id = ?2",
+            [head.id, department.id],
+        )?;
+        cyclic.drop_all(&conn)?;
+        let foreign_keys: bool = conn.query_row("PRAGMA foreign_keys", [], |row| row.get(0))?;
+        assert!(foreign_keys);
+        Database::open_in_memory()
+            .table::<Department>()
+            .table::<Employee>()
+            .connect()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn constraint_errors() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory()
+            .schema(schema![User, UserRole, Counter, Tag])
+            .connect()?;
+        assert_eq!(UserRole::FOREIGN_KEY_FIELDS, ["user_id"]);
+
+        let alice = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        let taken = User::new()
+            .with_name("Alice 2")
+            .with_email("ALICE@example.com")
+            .build(&conn)
+            .unwrap_err();
+        assert!(taken.is_unique::<User>(&["email"]));
+        assert_eq!(taken.to_string(), "User with this email already exists");
+
+        Tag::new()
+            .with_name("rust")
+            .with_scope("lang")
+            .with_uses(1)
+            .build(&conn)?;
+        let duplicate = Tag::new()
+            .with_name("rust")
+            .with_scope("lang")
+            .with_uses(2)
+            .build(&conn)
+            .unwrap_err();
+        assert!(
+            matches!(&duplicate, crate::Error::PrimaryKey { table, fields } if table == "Tag" && fields == &["name", "scope"]),
+            "{duplicate:?}"
+        );
+
+        let orphan = UserRole::new()
+            .with_user_id(alice.id + 1)
+            .with_role("admin")
+            .build(&conn)
+            .unwrap_err();
+        assert!(matches!(&orphan, crate::Error::ForeignKey { .. }));
+        assert_eq!(orphan.table(), Some(UserRole::TABLE_NAME));
+        // The only foreign key of UserRole, so the only candidate
+        assert_eq!(orphan.fields(), ["user_id"]);
+
+        // Deleting a referenced row fails on the deleted table
+        UserRole::new()
+            .with_user_id(alice.id)
+            .with_role("admin")
+            .build(&conn)?;
+        let referenced = User::delete(&conn, "WHERE id = ?1", [alice.id]).unwrap_err();
+        assert!(
+            matches!(&referenced, crate::Error::ForeignKey { table: Some(table), .. } if table == "User")
+        );
+
+        let not_null = conn
+            .execute(
+                "INSERT INTO User (name, email) VALUES (NULL, 'bob@example.com')",
+                [],
+            )
+            .map_err(DbError::for_table::<User>)
+            .unwrap_err();
+        assert!(
+            matches!(&not_null, crate::Error::NotNull { table, field } if table == "User" && field == "name")
+        );
+
+        let negative = conn
+            .execute("INSERT INTO Counter (hits, hash) VALUES (-1, '0')", [])
+            .map_err(DbError::for_table::<Counter>)
+            .unwrap_err();
+        assert_eq!(negative.table(), Some("Counter"));
+        assert_eq!(negative.fields(), ["hits"]);
+
+        // Without the table only what SQLite reports is known
+        let unknown = DbError::from(
+            conn.execute("INSERT INTO Counter (hits, hash) VALUES (-1, '0')", [])
+                .unwrap_err(),
+        );
+        assert!(matches!(&unknown, crate::Error::Check { table: None, .. }));
+        let not_constraint = DbError::from(conn.execute("SELECT * FROM Missing", []).unwrap_err());
+        assert!(matches!(not_constraint, crate::Error::Sqlite(_)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn validation() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory().table::<Member>().connect()?;
+        assert!(Member::create_table_str().contains("CHECK (age >= 0 AND age <= 120)"));
+
+        let mut member = Member::new()
+            .with_handle("alice")
+            .with_email("alice@example.com")
+            .with_age(30)
+            .build_val(&conn)?;
+
+        let invalid = Member::new()
+            .with_handle("")
+            .with_email("not an email")
+            .with_age(121)
+            .with_phone(Some("555-0100".to_string()))
+            .build(&conn)
+            .unwrap_err();
+        let crate::Error::Validation(errors) = &invalid else {
+            panic!("{invalid:?}");
+        };
+        let rules = errors
+            .errors
+            .iter()
+            .map(|e| (e.field, e.rule))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            [
+                ("handle", "length"),
+                ("email", "email"),
+                ("age", "range"),
+                ("phone", "regex")
+            ]
+        );
+        assert_eq!(
+            errors.field("age").next().unwrap().to_string(),
+            "age: must be in 0..=120"
+        );
+        assert_eq!(invalid.table(), Some("Member"));
+        assert_eq!(Member::select(&conn, "", [])?.len(), 1);
+
+        // Builders check the fields set so far
+        let partial = Member::new().with_handle("has space");
+        let errors = partial.validate().unwrap_err();
+        assert_eq!(errors.errors.len(), 1);
+        assert_eq!(
+            errors.to_string(),
+            "invalid Member: handle: must not contain spaces"
+        );
+
+        member.age = 200;
+        assert!(matches!(
+            member.update(&conn),
+            Err(crate::Error::Validation(_))
+        ));
+        member.age = 31;
+        member.phone = Some("+1 555 0100".to_string());
+        assert_eq!(member.update(&conn)?, 1);
+        let updated = Member::select_one(&conn, "WHERE id = ?1", [member.id])?.unwrap();
+        assert_eq!(updated.age, 31);
+        assert_eq!(updated.phone.as_deref(), Some("+1 555 0100"));
+
+        // `check` rules hold for writes bypassing the generated code too
+        let unchecked = conn
+            .execute("UPDATE Member SET age = 121 WHERE id = ?1", [member.id])
+            .map_err(DbError::for_table::<Member>)
+            .unwrap_err();
+        assert!(matches!(&unchecked, crate::Error::Check { .. }));
+        assert_eq!(unchecked.fields(), ["age"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_hooks() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory().table::<Account>().connect()?;
+
+        let mut alice = Account::new()
+            .with_email("  Alice@Example.com ")
+            .with_balance(10)
+            .build_val(&conn)?;
+        assert_eq!(alice.email, "alice@example.com");
+        let bob_id = Account::new()
+            .with_email("BOB@example.com")
+            .with_balance(0)
+            .build(&conn)?;
+        // Hooks run before validation, so they may fix values up
+        assert!(
+            Account::new()
+                .with_email(" not an email ")
+                .with_balance(0)
+                .build_raw(&conn)
+                .is_err()
+        );
+
+        alice.email = "ALICE@example.org".to_string();
+        alice.update(&conn)?;
+        assert_eq!(alice.email, "alice@example.org");
+
+        assert_eq!(
+            Account::explain_delete(&conn, "WHERE balance = 0", [])?,
+            Account::explain_select(&conn, "WHERE balance = 0", [])?
+        );
+        let refused = Account::delete(&conn, "ORDER BY balance", []).unwrap_err();
+        assert!(matches!(refused, crate::Error::Validation(_)));
+        // The refused row rolls back the rows deleted before it, without their after_delete
+        assert_eq!(Account::select(&conn, "", [])?.len(), 2);
+        assert_eq!(Account::delete(&conn, "WHERE id = ?1", [bob_id])?, 1);
+        alice.balance = 0;
+        alice.update(&conn)?;
+        assert_eq!(Account::delete(&conn, "WHERE id = ?1", [alice.id])?, 1);
+
+        let events = ACCOUNT_EVENTS.with_borrow(|events| events.clone());
+        assert_eq!(
+            events,
+            [
+                "inserted alice@example.com",
+                "inserted bob@example.com",
+                "updated alice@example.org",
+                "deleted bob@example.com",
+                "updated alice@example.org",
+                "deleted alice@example.org",
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory().table::<Note>().connect()?;
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let clock = crate::clock::MockClock::new(start);
+        let _guard = crate::clock::set_clock(clock.clone());
+
+        let mut note = Note::new().with_body("draft").build_val(&conn)?;
+        assert_eq!((note.created, note.updated), (start, start));
+
+        clock.advance(chrono::TimeDelta::hours(1));
+        note.body = "final".to_string();
+        note.update(&conn)?;
+        let stored = Note::select_one(&conn, "WHERE id = ?1", [note.id])?.unwrap();
+        assert_eq!(stored.created, start);
+        assert_eq!(stored.updated, start + chrono::TimeDelta::hours(1));
+        assert_eq!(note.updated, stored.updated);
+
+        // The trigger leaves the time alone when the clock didn't move
+        note.body = "final, really".to_string();
+        note.update(&conn)?;
+        let stored = Note::select_one(&conn, "WHERE id = ?1", [note.id])?.unwrap();
+        assert_eq!(stored.updated, start + chrono::TimeDelta::hours(1));
+        assert_eq!(note.updated, stored.updated);
+
+        // Raw updates get the database's time from the trigger
+        conn.execute("UPDATE Note SET body = 'edited' WHERE id = ?1", [note.id])?;
+        let stored = Note::select_one(&conn, "WHERE id = ?1", [note.id])?.unwrap();
+        assert!(stored.updated > start + chrono::TimeDelta::days(365));
+        assert!(Note::create_table_str().ends_with(')'));
+        assert!(Note::create_triggers_str().contains("CREATE TRIGGER IF NOT EXISTS Note_updated"));
+
+        // Dropping the table drops the one `update` holds the triggers off with
+        Note::drop_table(&conn)?;
+        let tables: i64 = conn.query_row(
+            "SELECT count(*) FROM sqlite_schema WHERE type = 'table'",
+            [],
+            |row| row.get(0),
+        )?;
+        assert_eq!(tables, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn soft_delete() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory().table::<Customer>().connect()?;
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 8, 30, 0).unwrap();
+        let _guard = crate::clock::set_clock(crate::clock::MockClock::new(now));
+        for name in ["Alice", "Bob", "Carol"] {
+            Customer::new().with_name(name).build(&conn)?;
+        }
+
+        assert_eq!(Customer::delete(&conn, "WHERE name = ?1", ["Bob"])?, 1);
+        assert_eq!(Customer::delete(&conn, "WHERE name = ?1", ["Bob"])?, 0);
+        let names = |customers: Box<[Customer]>| {
+            customers.iter().map(|c| c.name.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(Customer::select(&conn, "ORDER BY name", [])?),
+            ["Alice", "Carol"]
+        );
+        assert!(Customer::select_one(&conn, "WHERE name = ?1", ["Bob"])?.is_none());
+        assert_eq!(Customer::select(&conn, "WHERE ROWID = ?1", [3])?.len(), 1);
+
+        assert_eq!(Customer::with_deleted(&conn, "", [])?.len(), 3);
+        let deleted = Customer::only_deleted(&conn, "", [])?;
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].deleted_at, Some(now));
+
+        assert_eq!(Customer::restore(&conn, "WHERE name = ?1", ["Bob"])?, 1);
+        assert_eq!(Customer::select(&conn, "", [])?.len(), 3);
+
+        Customer::delete(&conn, "WHERE name = ?1", ["Carol"])?;
+        assert_eq!(Customer::purge(&conn, "WHERE name = ?1", ["Carol"])?, 1);
+        assert_eq!(
+            names(Customer::with_deleted(&conn, "ORDER BY name", [])?),
+            ["Alice", "Bob"]
+        );
+
+        // The plan is the one of the update, setting the rows a subquery finds by rowid
+        let plan = Customer::explain_delete(&conn, "WHERE name = ?1", ["Alice"])?;
+        assert!(matches!(
+            &plan.nodes[0].step,
+            crate::PlanStep::Search { table, index: Some(index), .. }
+                if table == "Customer" && index == "INTEGER PRIMARY KEY"
+        ));
+        assert!(
+            plan.iter()
+                .any(|node| node.detail.starts_with("LIST SUBQUERY"))
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn optimistic_locking() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory()
+            .table::<Document>()
+            .table::<without_prelude::Note>()
+            .connect()?;
+        let start = Utc.with_ymd_and_hms(2024, 3, 1, 9, 0, 0).unwrap();
+        let clock = crate::clock::MockClock::new(start);
+        let _guard = crate::clock::set_clock(clock.clone());
+        let mut first = Document::new().with_title("Draft").build_val(&conn)?;
+        assert_eq!(first.version, 1);
+        let mut second = first.clone();
+
+        clock.advance(chrono::TimeDelta::minutes(5));
+        first.title = "Final".to_string();
+        assert_eq!(first.update(&conn)?, 1);
+        assert_eq!(first.version, 2);
+        assert_eq!(first.edited, start + chrono::TimeDelta::minutes(5));
+
+        second.title = "Overwritten".to_string();
+        let stale = second.update(&conn).unwrap_err();
+        assert!(matches!(&stale, crate::Error::StaleVersion { table } if table == "Document"));
+        assert_eq!(stale.to_string(), "Document was changed since it was read");
+        // A stale update leaves the struct as it was
+        assert_eq!((second.version, second.edited), (1, start));
+
+        // and runs no `before_update` hook
+        let mut note = without_prelude::Note::new()
+            .with_text("note")
+            .build_val(&conn)?;
+        let mut stale_note = note.clone();
+        note.update(&conn)?;
+        stale_note.text = " stale ".to_string();
+        assert!(stale_note.update(&conn).is_err());
+        assert_eq!(
+            (stale_note.text.as_str(), stale_note.version),
+            (" stale ", 1)
+        );
+
+        let mut reloaded = Document::select_one(&conn, "WHERE id = ?1", [first.id])?.unwrap();
+        assert_eq!((reloaded.title.as_str(), reloaded.version), ("Final", 2));
+        reloaded.title = "Revised".to_string();
+        reloaded.update(&conn)?;
+        assert_eq!(reloaded.version, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn history() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory().table::<Article>().connect()?;
+        let mut article = Article::new().with_title("Draft").build_val(&conn)?;
+        let other = Article::new().with_title("Other").build_val(&conn)?;
+        let history = Article::history(&conn, article.id)?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].row.title, "Draft");
+        assert_eq!(history[0].operation, HistoryOperation::Insert);
+        let inserted_at = history[0].changed_at;
+
+        // The recorded times are the database's, in milliseconds, so keep them apart
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        article.title = "Final".to_string();
+        article.update(&conn)?;
+        let history = Article::history(&conn, article.id)?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].row.title, "Draft");
+        assert_eq!(history[1].operation, HistoryOperation::Update);
+        let updated_at = history[1].changed_at;
+
+        let before_insert = inserted_at - chrono::TimeDelta::milliseconds(1);
+        let before = updated_at - chrono::TimeDelta::milliseconds(1);
+        let later = updated_at + chrono::TimeDelta::hours(1);
+        assert!(Article::as_of(&conn, article.id, before_insert)?.is_none());
+        assert_eq!(
+            Article::as_of(&conn, article.id, before)?.unwrap().title,
+            "Draft"
+        );
+        assert_eq!(
+            Article::as_of(&conn, article.id, later)?.unwrap().title,
+            "Final"
+        );
+
+        Article::delete(&conn, "WHERE id = ?1", [article.id])?;
+        let history = Article::history(&conn, article.id)?;
+        assert_eq!(
+            history
+                .iter()
+                .map(|h| (h.row.title.as_str(), h.operation))
+                .collect::<Vec<_>>(),
+            [
+                ("Draft", HistoryOperation::Insert),
+                ("Draft", HistoryOperation::Update),
+                ("Final", HistoryOperation::Delete)
+            ]
+        );
+        assert!(Article::as_of(&conn, article.id, before_insert)?.is_none());
+        assert_eq!(
+            Article::as_of(&conn, article.id, before)?.unwrap().title,
+            "Draft"
+        );
+        assert!(Article::as_of(&conn, article.id, later)?.is_none());
+        assert_eq!(Article::history(&conn, other.id)?.len(), 1);
+
+        Article::drop_table(&conn)?;
+        assert_eq!(Article::history(&conn, article.id)?.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn subscriptions() -> Result<(), Box<dyn std::error::Error>> {
+        use std::sync::{Arc, Mutex};
+
+        let mut conn = Database::open_in_memory()
+            .table::<User>()
+            .table::<UserRole>()
+            .table::<Account>()
+            .connect()?;
+        let subscriptions = Subscriptions::install(&conn)?;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let events_id = subscriptions.subscribe::<User>(move |event| {
+            sink.lock().unwrap().push(event.clone());
+        });
+        let sink = rows.clone();
+        subscriptions.subscribe_rows::<User>(move |event, user: Option<&User>| {
+            sink.lock()
+                .unwrap()
+                .push((event.kind, user.map(|u| u.name.clone())));
+        });
+
+        let mut user = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        user.name = "Alicia".to_string();
+        user.update(&conn)?;
+        let rowid = i64::from(user.id);
+        assert!(events.lock().unwrap().is_empty());
+        assert!(rows.lock().unwrap().is_empty());
+        assert_eq!(subscriptions.deliver(&conn)?, 2);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                ChangeEvent {
+                    kind: ChangeKind::Insert,
+                    rowid
+                },
+                ChangeEvent {
+                    kind: ChangeKind::Update,
+                    rowid
+                },
+            ]
+        );
+        assert_eq!(
+            *rows.lock().unwrap(),
+            [
+                (ChangeKind::Insert, Some("Alicia".to_string())),
+                (ChangeKind::Update, Some("Alicia".to_string())),
+            ]
+        );
+        assert_eq!(subscriptions.deliver(&conn)?, 0);
+
+        // Other tables, rolled back and uncommitted changes aren't delivered
+        UserRole::new()
+            .with_user_id(user.id)
+            .with_role("Admin")
+            .build(&conn)?;
+        let tx = conn.transaction()?;
+        User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build(&tx)?;
+        tx.rollback()?;
+        let tx = conn.transaction()?;
+        User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build(&tx)?;
+        assert_eq!(subscriptions.deliver(&tx)?, 0);
+        tx.commit()?;
+        assert_eq!(subscriptions.deliver(&conn)?, 1);
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        // Nor are the ones undone by `ROLLBACK TO`, but a failed statement keeps earlier commits
+        let mut tx = conn.transaction()?;
+        {
+            let mut savepoint = tx.savepoint()?;
+            User::new()
+                .with_name("Dave")
+                .with_email("dave@example.com")
+                .build(&savepoint)?;
+            savepoint.rollback()?;
+        }
+        tx.commit()?;
+        let accounts = Arc::new(Mutex::new(Vec::new()));
+        let sink = accounts.clone();
+        subscriptions.subscribe::<Account>(move |event| sink.lock().unwrap().push(event.kind));
+        for balance in [0, 10] {
+            Account::new()
+                .with_email("account@example.com")
+                .with_balance(balance)
+                .build(&conn)?;
+        }
+        assert!(Account::delete(&conn, "ORDER BY balance", []).is_err());
+        assert!(
+            conn.execute("INSERT INTO User (id) VALUES (?1)", [user.id])
+                .is_err()
+        );
+        assert_eq!(subscriptions.deliver(&conn)?, 2);
+        assert_eq!(*accounts.lock().unwrap(), [ChangeKind::Insert; 2]);
+        assert_eq!(events.lock().unwrap().len(), 3);
+
+        // Callbacks may use the subscriptions, here to unsubscribe themselves
+        let calls = Arc::new(Mutex::new(0));
+        let own_id = Arc::new(Mutex::new(None));
+        let (inner, counter, own) = (subscriptions.clone(), calls.clone(), own_id.clone());
+        let once_id = subscriptions.subscribe::<User>(move |_| {
+            *counter.lock().unwrap() += 1;
+            if let Some(id) = own.lock().unwrap().take() {
+                assert!(inner.unsubscribe(id));
+            }
+        });
+        *own_id.lock().unwrap() = Some(once_id);
+
+        assert!(subscriptions.unsubscribe(events_id));
+        assert!(!subscriptions.unsubscribe(events_id));
+        UserRole::delete(&conn, "", [])?;
+        User::delete(&conn, "WHERE id = ?1", [user.id])?;
+        assert_eq!(subscriptions.deliver(&conn)?, 1);
+        assert_eq!(events.lock().unwrap().len(), 3);
+        assert_eq!(rows.lock().unwrap()[3], (ChangeKind::Delete, None));
+        User::delete(&conn, "", [])?;
+        assert_eq!(subscriptions.deliver(&conn)?, 1);
+        assert_eq!(*calls.lock().unwrap(), 1);
+        assert!(!subscriptions.unsubscribe(once_id));
+
+        subscriptions.uninstall(&conn)?;
+        User::new()
+            .with_name("Carol")
+            .with_email("carol@example.com")
+            .build(&conn)?;
+        assert_eq!(subscriptions.deliver(&conn)?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn full_text_search() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = Database::open_in_memory()
+            .fts_table::<UserSearch>()
+            .table::<User>()
+            .connect()?;
+        let names = |rows: Box<[(User, f64)]>| {
+            rows.iter()
+                .map(|(user, _)| user.name.clone())
+                .collect::<Vec<_>>()
+        };
+        let mut alice = User::new()
+            .with_name("Alice Smith")
+            .with_email("alice@example.com")
+            .build_val(&conn)?;
+        User::new()
+            .with_name("Bob Smith")
+            .with_email("bob@example.org")
+            .build(&conn)?;
+
+        assert_eq!(names(UserSearch::search(&conn, "alice")?), ["Alice Smith"]);
+        let smiths = UserSearch::search(&conn, "smith")?;
+        assert_eq!(smiths.len(), 2);
+        assert!(smiths.iter().all(|(_, rank)| *rank < 0.0));
+        assert_eq!(
+            names(UserSearch::search(&conn, "email:org")?),
+            ["Bob Smith"]
+        );
+
+        alice.name = "Alicia Jones".to_string();
+        alice.update(&conn)?;
+        assert!(UserSearch::search(&conn, "smith AND alice")?.is_empty());
+        assert_eq!(names(UserSearch::search(&conn, "jones")?), ["Alicia Jones"]);
+        // Only updates of the indexed columns reindex the row
+        assert!(UserSearch::create_table_str().contains("AFTER UPDATE OF name, email ON User"));
+        const { assert!(!User::WITHOUT_ROWID && Tag::WITHOUT_ROWID) };
+
+        let highlighted = UserSearch::highlight(&conn, "jones", "name", "[", "]")?;
+        assert_eq!(highlighted[0].1, "Alicia [Jones]");
+        let snippets = UserSearch::snippet(&conn, "bob", "email", "<", ">", "...", 2)?;
+        assert_eq!(snippets[0].1, "<bob>@example...");
+        assert!(UserSearch::highlight(&conn, "jones", "created_date", "[", "]").is_err());
+
+        User::delete(&conn, "WHERE id = ?1", [alice.id])?;
+        assert!(UserSearch::search(&conn, "jones")?.is_empty());
+
+        // A new FTS table only indexes the existing rows once rebuilt
+        UserSearch::drop_table(&conn)?;
+        User::new()
+            .with_name("Carol Smith")
+            .with_email("carol@example.com")
+            .build(&conn)?;
+        UserSearch::create_table(&conn)?;
+        assert!(UserSearch::search(&conn, "smith")?.is_empty());
+        UserSearch::rebuild(&conn)?;
+        assert_eq!(UserSearch::search(&conn, "smith")?.len(), 2);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_connection() -> Result<(), Box<dyn std::error::Error>> {
+        let conn = AsyncConnection::open_in_memory().await?;
+        conn.create_table::<User>().await?;
+        conn.create_table::<UserTeam>().await?;
+
+        let alice = User::new()
+            .with_name("Alice")
+            .with_email("alice@example.com")
+            .build_val_async(&conn)
+            .await?;
+        let bob = User::new()
+            .with_name("Bob")
+            .with_email("bob@example.com")
+            .build_async(&conn)
+            .await?;
+        UserTeam::new()
+            .with_team_leader(alice.id)
+            .with_team_member(bob as Id)
+            .build_raw_async(&conn)
+            .await?;
+
+        let users = conn.select::<User>("ORDER BY id", []).await?;
+        assert_eq!(users.len(), 2);
+        let found = conn
+            .select_one::<User>("WHERE name = ?1", ["Bob".to_string()])
+            .await?;
+        assert_eq!(found.map(|u| u.email), Some("bob@example.com".to_string()));
+
+        // Clones share the connection, so they see the same in-memory database
+        let reports = conn
+            .clone()
+            .select_cte::<Report>(ReportParams { user_id: alice.id })
+            .await?;
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].name, "Bob");
+
+        assert_eq!(conn.delete::<User>("WHERE id = ?1", [bob]).await?, 1);
+        let panicked = tokio::spawn({
+            let conn = conn.clone();
+            async move {
+                conn.call(|_| -> rusqlite::Result<()> { panic!("in job") })
+                    .await
+            }
+        })
+        .await;
+        assert!(panicked.is_err());
+        // The connection thread survives panicking jobs
+        assert_eq!(conn.select::<User>("", []).await?.len(), 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,351 @@
+//! A fixed-size pool of SQLite connections for multi-threaded services.
+//!
+//! The pool holds a single writer and several read-only readers of one database file. In WAL mode
+//! the readers keep working while the writer commits, and as all writes go through the one writer
+//! they never wait on each other's locks. Checked out connections deref to
+//! [`rusqlite::Connection`], so they can be passed to [`DbTable`](crate::DbTable) and builder
+//! methods directly.
+//!
+//! ```ignore
+//! let pool = Pool::builder("app.db").readers(4).create_table::<User>().build()?;
+//! let writer = pool.writer()?;
+//! User::new().with_name("Alice").build(&writer)?;
+//! let reader = pool.reader()?;
+//! let users = User::select(&reader, "", [])?;
+//! ```
+
+use std::{
+    fmt::Display,
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, PoisonError},
+    time::Duration,
+};
+
+use rusqlite::{Connection, OpenFlags};
+
+use crate::{DbTable, database::configure};
+
+type InitFn = Box<dyn Fn(&Connection) -> rusqlite::Result<()> + Send + Sync>;
+
+#[derive(Debug)]
+pub enum PoolError {
+    /// No connection was returned to the pool within the checkout timeout.
+    Timeout,
+    Db(crate::Error),
+}
+
+impl Display for PoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PoolError::Timeout => write!(f, "timed out waiting for a pooled connection"),
+            PoolError::Db(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for PoolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PoolError::Timeout => None,
+            PoolError::Db(err) => Some(err),
+        }
+    }
+}
+
+impl From<crate::Error> for PoolError {
+    fn from(err: crate::Error) -> Self {
+        PoolError::Db(err)
+    }
+}
+
+impl From<rusqlite::Error> for PoolError {
+    fn from(err: rusqlite::Error) -> Self {
+        PoolError::Db(err.into())
+    }
+}
+
+/// Configures and opens a [`Pool`], see [`Pool::builder`].
+pub struct PoolBuilder {
+    path: PathBuf,
+    readers: usize,
+    wal: bool,
+    foreign_keys: bool,
+    checkout_timeout: Duration,
+    health_check: bool,
+    pragmas: Vec<(String, String)>,
+    init: Vec<InitFn>,
+    tables: Vec<fn(&Connection) -> crate::Result<usize>>,
+}
+
+impl PoolBuilder {
+    /// The number of read-only connections, 4 by default.
+    pub fn readers(mut self, readers: usize) -> Self {
+        self.readers = readers;
+        self
+    }
+
+    /// Puts the database in WAL mode so readers don't block on the writer, on by default.
+    pub fn wal(mut self, wal: bool) -> Self {
+        self.wal = wal;
+        self
+    }
+
+    /// Runs `PRAGMA foreign_keys = ON` on every connection, on by default.
+    pub fn foreign_keys(mut self, foreign_keys: bool) -> Self {
+        self.foreign_keys = foreign_keys;
+        self
+    }
+
+    /// How long [`Pool::reader`] and [`Pool::writer`] wait for a free connection, 30 seconds by
+    /// default. Also used as every connection's busy timeout.
+    pub fn checkout_timeout(mut self, timeout: Duration) -> Self {
+        self.checkout_timeout = timeout;
+        self
+    }
+
+    /// Runs `SELECT 1` on every checkout and reopens connections that fail it, off by default.
+    pub fn health_check(mut self, health_check: bool) -> Self {
+        self.health_check = health_check;
+        self
+    }
+
+    /// Runs `PRAGMA name = value` on every connection.
+    pub fn pragma(mut self, name: &str, value: impl Display) -> Self {
+        self.pragmas.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Runs `init` on every connection after the pragmas, e.g. to register functions.
+    pub fn on_connect(
+        mut self,
+        init: impl Fn(&Connection) -> rusqlite::Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.init.push(Box::new(init));
+        self
+    }
+
+    /// Creates the table through the writer when the pool is built. Tables are created in the
+    /// order they are added.
+    pub fn create_table<T: DbTable>(mut self) -> Self
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        self.tables.push(T::create_table);
+        self
+    }
+
+    pub fn build(self) -> Result<Pool, PoolError> {
+        let pool = PoolInner {
+            path: self.path,
+            wal: self.wal,
+            foreign_keys: self.foreign_keys,
+            checkout_timeout: self.checkout_timeout,
+            health_check: self.health_check,
+            pragmas: self.pragmas,
+            init: self.init,
+            writer: Slots::default(),
+            readers: Slots::default(),
+        };
+
+        // The writer goes first, so the file exists and is in WAL mode before readers open it
+        let writer = pool.open(false)?;
+        for create_table in &self.tables {
+            create_table(&writer)?;
+        }
+        pool.writer.give_back(writer);
+        for _ in 0..self.readers {
+            let reader = pool.open(true)?;
+            pool.readers.give_back(reader);
+        }
+
+        Ok(Pool {
+            inner: Arc::new(pool),
+        })
+    }
+}
+
+#[derive(Default)]
+struct Slots {
+    idle: Mutex<Vec<Connection>>,
+    returned: Condvar,
+}
+
+impl Slots {
+    fn give_back(&self, conn: Connection) {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(conn);
+        self.returned.notify_one();
+    }
+}
+
+struct PoolInner {
+    path: PathBuf,
+    wal: bool,
+    foreign_keys: bool,
+    checkout_timeout: Duration,
+    health_check: bool,
+    pragmas: Vec<(String, String)>,
+    init: Vec<InitFn>,
+    writer: Slots,
+    readers: Slots,
+}
+
+impl PoolInner {
+    fn slots(&self, read_only: bool) -> &Slots {
+        if read_only {
+            &self.readers
+        } else {
+            &self.writer
+        }
+    }
+
+    fn open(&self, read_only: bool) -> rusqlite::Result<Connection> {
+        // Both open the path the same way, so `file:` URIs name one database
+        let access = if read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE
+        };
+        let conn = Connection::open_with_flags(
+            &self.path,
+            access | OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        configure(
+            &conn,
+            Some(self.checkout_timeout),
+            self.wal && !read_only,
+            self.foreign_keys,
+            &self.pragmas,
+        )?;
+        for init in &self.init {
+            init(&conn)?;
+        }
+        Ok(conn)
+    }
+
+    fn is_healthy(conn: &Connection) -> bool {
+        conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))
+            .is_ok()
+    }
+}
+
+/// A fixed-size pool with one writer and several readers. Cloning the pool is cheap and shares
+/// its connections.
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<PoolInner>,
+}
+
+impl Pool {
+    /// A pool for the database file at `path`. Every connection of an in-memory database would
+    /// be its own database, so a file is needed.
+    pub fn builder(path: impl AsRef<Path>) -> PoolBuilder {
+        PoolBuilder {
+            path: path.as_ref().to_path_buf(),
+            readers: 4,
+            wal: true,
+            foreign_keys: true,
+            checkout_timeout: Duration::from_secs(30),
+            health_check: false,
+            pragmas: Vec::new(),
+            init: Vec::new(),
+            tables: Vec::new(),
+        }
+    }
+
+    /// Checks out a read-only connection, waiting up to the checkout timeout for one to be free.
+    pub fn reader(&self) -> Result<PooledConnection, PoolError> {
+        self.checkout(true)
+    }
+
+    /// Checks out the writer, waiting up to the checkout timeout for it to be free.
+    pub fn writer(&self) -> Result<PooledConnection, PoolError> {
+        self.checkout(false)
+    }
+
+    /// The number of connections not checked out right now, as `(writers, readers)`.
+    pub fn idle(&self) -> (usize, usize) {
+        let count = |slots: &Slots| {
+            slots
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len()
+        };
+        (count(&self.inner.writer), count(&self.inner.readers))
+    }
+
+    fn checkout(&self, read_only: bool) -> Result<PooledConnection, PoolError> {
+        let slots = self.inner.slots(read_only);
+        let idle = slots.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let (mut idle, _) = slots
+            .returned
+            .wait_timeout_while(idle, self.inner.checkout_timeout, |idle| idle.is_empty())
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut conn = idle.pop().ok_or(PoolError::Timeout)?;
+        drop(idle);
+
+        if self.inner.health_check && !PoolInner::is_healthy(&conn) {
+            match self.inner.open(read_only) {
+                Ok(fresh) => conn = fresh,
+                Err(err) => {
+                    // Keep the pool at its size, the next checkout tries again
+                    slots.give_back(conn);
+                    return Err(err.into());
+                }
+            }
+        }
+
+        Ok(PooledConnection {
+            conn: Some(conn),
+            read_only,
+            pool: Arc::clone(&self.inner),
+        })
+    }
+}
+
+/// A connection checked out of a [`Pool`], returned to it when dropped. Transactions left open
+/// are rolled back first.
+pub struct PooledConnection {
+    conn: Option<Connection>,
+    read_only: bool,
+    pool: Arc<PoolInner>,
+}
+
+impl PooledConnection {
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.conn
+            .as_mut()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            if !conn.is_autocommit() {
+                let _ = conn.execute_batch("ROLLBACK;");
+            }
+            self.pool.slots(self.read_only).give_back(conn);
+        }
+    }
+}
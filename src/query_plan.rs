@@ -0,0 +1,188 @@
+use std::fmt::Display;
+
+/// What a row of `EXPLAIN QUERY PLAN` does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanStep {
+    /// Reads every row of `table`, in the order of `index` if there is one.
+    Scan {
+        table: String,
+        index: Option<String>,
+    },
+    /// Looks rows of `table` up through `index`. `automatic` indexes are built by scanning the
+    /// whole table for this one query.
+    Search {
+        table: String,
+        index: Option<String>,
+        automatic: bool,
+    },
+    /// Reads the rows a `CO-ROUTINE` or `MATERIALIZE` step of the plan builds for a CTE or a
+    /// subquery, e.g. the `params` and `tree` tables generated for CTEs.
+    ScanSubquery { name: String },
+    /// Anything else, e.g. `SCALAR SUBQUERY 1` or `USE TEMP B-TREE FOR ORDER BY`.
+    Other,
+}
+
+impl PlanStep {
+    /// `subqueries` are the names the plan's `CO-ROUTINE` and `MATERIALIZE` steps build.
+    pub(crate) fn parse(detail: &str, subqueries: &[&str]) -> Self {
+        let (step, rest) = detail.split_once(' ').unwrap_or((detail, ""));
+        if (step != "SCAN" && step != "SEARCH") || rest == "CONSTANT ROW" {
+            return PlanStep::Other;
+        }
+        // Before SQLite 3.36 tables were reported as `TABLE x` and subqueries as `SUBQUERY n`
+        let rest = rest
+            .strip_prefix("TABLE ")
+            .or_else(|| rest.strip_prefix("SUBQUERY "))
+            .unwrap_or(rest);
+        let (table, using) = match rest.split_once(' ') {
+            Some((table, using)) => (table.to_string(), using),
+            None => (rest.to_string(), ""),
+        };
+        if step == "SCAN" && subqueries.contains(&table.as_str()) {
+            return PlanStep::ScanSubquery { name: table };
+        }
+        let using = using.strip_prefix("USING ").unwrap_or("");
+        let automatic = using.starts_with("AUTOMATIC");
+        let index = if automatic || using.is_empty() {
+            None
+        } else if let Some(pk) = ["INTEGER PRIMARY KEY", "PRIMARY KEY"]
+            .into_iter()
+            .find(|pk| using.starts_with(pk))
+        {
+            Some(pk.to_string())
+        } else {
+            using
+                .trim_start_matches("COVERING ")
+                .strip_prefix("INDEX ")
+                .and_then(|i| i.split_whitespace().next())
+                .map(str::to_string)
+        };
+        if step == "SCAN" {
+            PlanStep::Scan { table, index }
+        } else {
+            PlanStep::Search {
+                table,
+                index,
+                automatic,
+            }
+        }
+    }
+
+    /// Scans without an index and searches through an automatic index both read the full table.
+    pub fn is_full_scan(&self) -> bool {
+        match self {
+            PlanStep::Scan { index, .. } => index.is_none(),
+            PlanStep::Search { automatic, .. } => *automatic,
+            PlanStep::ScanSubquery { .. } | PlanStep::Other => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPlanNode {
+    pub id: i64,
+    pub detail: String,
+    pub step: PlanStep,
+    pub children: Vec<QueryPlanNode>,
+}
+
+/// The tree reported by `EXPLAIN QUERY PLAN`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct QueryPlan {
+    pub nodes: Vec<QueryPlanNode>,
+}
+
+impl QueryPlan {
+    pub fn explain(
+        conn: &rusqlite::Connection,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> crate::Result<Self> {
+        let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {sql}"))?;
+        let rows = stmt
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let subqueries = rows
+            .iter()
+            .filter_map(|(_, _, detail)| {
+                detail
+                    .strip_prefix("CO-ROUTINE ")
+                    .or_else(|| detail.strip_prefix("MATERIALIZE "))
+            })
+            .collect::<Vec<_>>();
+        Ok(Self {
+            nodes: Self::children_of(0, &rows, &subqueries),
+        })
+    }
+
+    fn children_of(
+        parent: i64,
+        rows: &[(i64, i64, String)],
+        subqueries: &[&str],
+    ) -> Vec<QueryPlanNode> {
+        rows.iter()
+            .filter(|(_, p, _)| *p == parent)
+            .map(|(id, _, detail)| QueryPlanNode {
+                id: *id,
+                detail: detail.clone(),
+                step: PlanStep::parse(detail, subqueries),
+                children: Self::children_of(*id, rows, subqueries),
+            })
+            .collect()
+    }
+
+    /// Every node of the plan, parents before their children.
+    pub fn iter(&self) -> impl Iterator<Item = &QueryPlanNode> {
+        let mut stack = self.nodes.iter().rev().collect::<Vec<_>>();
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    pub fn full_scans(&self) -> Vec<&QueryPlanNode> {
+        self.iter()
+            .filter(|node| node.step.is_full_scan())
+            .collect()
+    }
+
+    /// Panics if any table is read without an index, for locking in index usage in tests.
+    #[track_caller]
+    pub fn assert_no_full_scan(&self) {
+        let scans = self.full_scans();
+        if !scans.is_empty() {
+            let details = scans
+                .iter()
+                .map(|node| node.detail.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            panic!("query plan has full table scans ({details}):\n{self}");
+        }
+    }
+}
+
+impl Display for QueryPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn write_nodes(
+            f: &mut std::fmt::Formatter<'_>,
+            nodes: &[QueryPlanNode],
+            depth: usize,
+        ) -> std::fmt::Result {
+            for node in nodes {
+                writeln!(f, "{}{}", "  ".repeat(depth), node.detail)?;
+                write_nodes(f, &node.children, depth + 1)?;
+            }
+            Ok(())
+        }
+        writeln!(f, "QUERY PLAN")?;
+        write_nodes(f, &self.nodes, 1)
+    }
+}
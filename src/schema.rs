@@ -0,0 +1,215 @@
+use rusqlite::Connection;
+
+use crate::{DbFtsTable, DbTable, Result};
+
+#[derive(Debug, Clone, Copy)]
+struct SchemaTable {
+    name: &'static str,
+    foreign_tables: &'static [&'static str],
+    create_table_str: fn() -> String,
+    create_triggers_str: fn() -> String,
+    create: fn(&Connection) -> Result<usize>,
+    drop: fn(&Connection) -> Result<usize>,
+}
+
+/// A set of tables created and dropped together, ordered by their `#[foreign_key]`s.
+///
+/// Foreign keys to tables outside the schema and to the table itself don't affect the order.
+/// Tables referencing each other in a cycle keep their registration order, and
+/// [`Schema::cycles`] lists them.
+/// Build one with [`schema!`](crate::schema!) or [`Schema::table`].
+#[derive(Debug, Clone, Default)]
+pub struct Schema {
+    tables: Vec<SchemaTable>,
+}
+
+/// `schema![User, UserRole, UserTeam]` is a [`Schema`] of the listed tables.
+#[macro_export]
+macro_rules! schema {
+    ($($table:ty),* $(,)?) => {
+        $crate::Schema::new()$(.table::<$table>())*
+    };
+}
+
+impl Schema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the table, unless it's already part of the schema.
+    pub fn table<T: DbTable>(mut self) -> Self
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        if !self.tables.iter().any(|t| t.name == T::TABLE_NAME) {
+            self.tables.push(SchemaTable {
+                name: T::TABLE_NAME,
+                foreign_tables: T::FOREIGN_TABLES,
+                create_table_str: T::create_table_str,
+                create_triggers_str: T::create_triggers_str,
+                create: T::create_table,
+                drop: T::drop_table,
+            });
+        }
+        self
+    }
+
+    /// Adds the FTS table, created after its content table when that's part of the schema.
+    pub fn fts_table<F: DbFtsTable>(mut self) -> Self
+    where
+        rusqlite::Error: for<'a> From<<F::Content as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        if !self.tables.iter().any(|t| t.name == F::TABLE_NAME) {
+            self.tables.push(SchemaTable {
+                name: F::TABLE_NAME,
+                foreign_tables: &[<F::Content as DbTable>::TABLE_NAME],
+                create_table_str: F::create_table_str,
+                create_triggers_str: String::new,
+                create: F::create_table,
+                drop: F::drop_table,
+            });
+        }
+        self
+    }
+
+    /// Adds the tables of `other` that aren't part of the schema yet.
+    pub(crate) fn merge(mut self, other: Schema) -> Self {
+        for table in other.tables {
+            if !self.tables.iter().any(|t| t.name == table.name) {
+                self.tables.push(table);
+            }
+        }
+        self
+    }
+
+    /// The table names, each after the tables it references.
+    pub fn table_names(&self) -> Vec<&'static str> {
+        self.sorted().iter().map(|t| t.name).collect()
+    }
+
+    /// Creates every table, referenced tables first.
+    pub fn create_all(&self, conn: &Connection) -> Result<()> {
+        for table in self.sorted() {
+            (table.create)(conn)?;
+        }
+        Ok(())
+    }
+
+    /// Drops every table, referencing tables first. Foreign keys are turned off meanwhile, as
+    /// tables in a cycle reference a table dropped before them, and restored afterwards. SQLite
+    /// ignores that inside a transaction.
+    pub fn drop_all(&self, conn: &Connection) -> Result<()> {
+        let foreign_keys: bool = conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))?;
+        conn.pragma_update(None, "foreign_keys", false)?;
+        let dropped = self
+            .sorted()
+            .iter()
+            .rev()
+            .try_for_each(|table| (table.drop)(conn).map(|_| ()));
+        conn.pragma_update(None, "foreign_keys", foreign_keys)?;
+        dropped
+    }
+
+    /// The statements of [`Schema::create_all`] as one script.
+    pub fn full_ddl(&self) -> String {
+        self.sorted()
+            .iter()
+            .flat_map(|t| [(t.create_table_str)(), (t.create_triggers_str)()])
+            .filter(|sql| !sql.is_empty())
+            .map(|sql| format!("{};\n", sql.trim_end_matches(';')))
+            .collect()
+    }
+
+    /// The groups of tables referencing each other in a cycle, each in registration order.
+    /// [`Schema::create_all`] creates them in that order too, and [`Schema::drop_all`] turns
+    /// foreign keys off to drop them.
+    pub fn cycles(&self) -> Vec<Vec<&'static str>> {
+        let references = self.references();
+        let mut grouped = vec![false; self.tables.len()];
+        let mut cycles = Vec::new();
+        for i in 0..self.tables.len() {
+            if grouped[i] || !reaches(&references, i, i) {
+                continue;
+            }
+            let cycle = (i..self.tables.len())
+                .filter(|&j| j == i || (reaches(&references, i, j) && reaches(&references, j, i)))
+                .inspect(|&j| grouped[j] = true)
+                .map(|j| self.tables[j].name)
+                .collect();
+            cycles.push(cycle);
+        }
+        cycles
+    }
+
+    /// The indexes of the tables each table references, leaving out itself and tables outside
+    /// the schema.
+    fn references(&self) -> Vec<Vec<usize>> {
+        self.tables
+            .iter()
+            .map(|table| {
+                table
+                    .foreign_tables
+                    .iter()
+                    .filter(|foreign| **foreign != table.name)
+                    .filter_map(|foreign| self.tables.iter().position(|t| t.name == *foreign))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Depth first topological sort, keeping the registration order where the foreign keys
+    /// allow it. Within a cycle only references to tables registered earlier are followed.
+    fn sorted(&self) -> Vec<&SchemaTable> {
+        let references = self.references();
+
+        fn visit(
+            i: usize,
+            dependencies: &[Vec<usize>],
+            placed: &mut [bool],
+            sorted: &mut Vec<usize>,
+        ) {
+            if std::mem::replace(&mut placed[i], true) {
+                return;
+            }
+            for &dependency in &dependencies[i] {
+                visit(dependency, dependencies, placed, sorted);
+            }
+            sorted.push(i);
+        }
+
+        let dependencies = references
+            .iter()
+            .enumerate()
+            .map(|(i, foreign)| {
+                foreign
+                    .iter()
+                    .copied()
+                    .filter(|&foreign| foreign < i || !reaches(&references, foreign, i))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut placed = vec![false; self.tables.len()];
+        let mut sorted = Vec::with_capacity(self.tables.len());
+        for i in 0..self.tables.len() {
+            visit(i, &dependencies, &mut placed, &mut sorted);
+        }
+        sorted.into_iter().map(|i| &self.tables[i]).collect()
+    }
+}
+
+/// Whether table `from` references table `to`, directly or through other tables.
+fn reaches(references: &[Vec<usize>], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; references.len()];
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        for &next in &references[i] {
+            if next == to {
+                return true;
+            }
+            if !std::mem::replace(&mut seen[next], true) {
+                stack.push(next);
+            }
+        }
+    }
+    false
+}
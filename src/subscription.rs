@@ -0,0 +1,355 @@
+//! Typed change notifications, built on SQLite's update, commit and rollback hooks and its
+//! authorizer.
+//!
+//! ```ignore
+//! let subscriptions = Subscriptions::install(&conn)?;
+//! subscriptions.subscribe::<User>(|event| println!("{:?} user {}", event.kind, event.rowid));
+//! subscriptions.subscribe_rows::<User>(|event, user| println!("{:?}: {user:?}", event.kind));
+//! User::new().with_name("Alice").build(&conn)?;
+//! subscriptions.deliver(&conn)?;
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use rusqlite::{
+    Connection,
+    hooks::{Action, AuthAction, AuthContext, Authorization, TransactionOperation},
+};
+
+use crate::{DbTable, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Insert,
+    Update,
+    Delete,
+}
+
+/// A committed change to a row of a subscribed table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    pub kind: ChangeKind,
+    pub rowid: i64,
+}
+
+/// Returned by [`Subscriptions::subscribe`] and [`Subscriptions::subscribe_rows`] to
+/// unsubscribe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+type Callback = Box<dyn FnMut(&ChangeEvent) + Send>;
+type RowCallback = Box<dyn FnMut(&Connection, &ChangeEvent) -> Result<()> + Send>;
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    /// Changes of the open transaction, dropped on rollback.
+    pending: Vec<(String, ChangeEvent)>,
+    /// The open savepoints, innermost last, with the length of `pending` when they started.
+    savepoints: Vec<(String, usize)>,
+    /// Changes of a transaction whose commit started, dropped if it fails and is rolled back.
+    committing: Vec<(String, ChangeEvent)>,
+    /// Committed changes waiting for [`Subscriptions::deliver`].
+    committed: Vec<(String, ChangeEvent)>,
+    subscribers: HashMap<String, Vec<(SubscriptionId, Callback)>>,
+    row_subscribers: HashMap<String, Vec<(SubscriptionId, RowCallback)>>,
+    /// The subscriptions taken out of the registry by a running [`Subscriptions::deliver`], and
+    /// the ones of them unsubscribed meanwhile.
+    delivering: Vec<(String, SubscriptionId)>,
+    unsubscribed: Vec<SubscriptionId>,
+}
+
+impl Registry {
+    fn next_id(&mut self) -> SubscriptionId {
+        self.next_id += 1;
+        SubscriptionId(self.next_id)
+    }
+
+    fn is_subscribed(&self, table: &str) -> bool {
+        self.subscribers.contains_key(table)
+            || self.row_subscribers.contains_key(table)
+            || self.delivering.iter().any(|(t, _)| t == table)
+    }
+
+    /// The commit of the changes in `committing` succeeded.
+    fn committed(&mut self) {
+        let committing = std::mem::take(&mut self.committing);
+        self.committed.extend(committing);
+    }
+
+    fn savepoint(&self, name: &str) -> Option<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint.eq_ignore_ascii_case(name))
+    }
+
+    /// Tracks the statement being prepared. A failed commit leaves its transaction open until
+    /// `COMMIT` is retried or `ROLLBACK` ends it, so any other statement means it went through.
+    fn prepare(&mut self, action: AuthAction<'_>) {
+        match action {
+            AuthAction::Transaction {
+                operation: TransactionOperation::Rollback | TransactionOperation::Unknown,
+            } => return,
+            AuthAction::Savepoint {
+                operation: TransactionOperation::Rollback,
+                savepoint_name,
+            } => {
+                if let Some(index) = self.savepoint(savepoint_name) {
+                    let len = self.savepoints[index].1;
+                    self.pending.truncate(len);
+                    self.savepoints.truncate(index + 1);
+                }
+                return;
+            }
+            AuthAction::Savepoint {
+                operation: TransactionOperation::Begin,
+                savepoint_name,
+            } => {
+                let len = self.pending.len();
+                self.savepoints.push((savepoint_name.to_string(), len));
+            }
+            AuthAction::Savepoint {
+                operation: TransactionOperation::Release,
+                savepoint_name,
+            } => {
+                if let Some(index) = self.savepoint(savepoint_name) {
+                    self.savepoints.truncate(index);
+                }
+            }
+            _ => {}
+        }
+        self.committed();
+    }
+}
+
+/// The subscriptions of a connection, whose hooks and authorizer it replaces. Changes are queued
+/// until their transaction commits and delivered by [`Subscriptions::deliver`], and only for
+/// rowid tables, as SQLite doesn't report changes to `WITHOUT ROWID` tables.
+///
+/// Changes undone by `ROLLBACK TO` a savepoint are dropped, such as the rows a refused
+/// [`DbTable::delete`] of a `#[hooks]` table restores. The authorizer only sees statements as
+/// they're prepared, so savepoints must not be run from the statement cache.
+#[derive(Clone)]
+pub struct Subscriptions {
+    registry: Arc<Mutex<Registry>>,
+}
+
+impl Subscriptions {
+    /// Sets the update, commit and rollback hooks and the authorizer of the connection.
+    pub fn install(conn: &Connection) -> Result<Self> {
+        let registry = Arc::new(Mutex::new(Registry::default()));
+
+        let hook_registry = registry.clone();
+        conn.update_hook(Some(
+            move |action: Action, _db: &str, table: &str, rowid: i64| {
+                let kind = match action {
+                    Action::SQLITE_INSERT => ChangeKind::Insert,
+                    Action::SQLITE_UPDATE => ChangeKind::Update,
+                    Action::SQLITE_DELETE => ChangeKind::Delete,
+                    _ => return,
+                };
+                let mut registry = lock(&hook_registry);
+                if registry.is_subscribed(table) {
+                    registry
+                        .pending
+                        .push((table.to_string(), ChangeEvent { kind, rowid }));
+                }
+            },
+        ))?;
+
+        // The commit may still fail after the hook, so the changes wait for it to finish
+        let hook_registry = registry.clone();
+        conn.commit_hook(Some(move || {
+            let mut registry = lock(&hook_registry);
+            let pending = std::mem::take(&mut registry.pending);
+            registry.committing.extend(pending);
+            registry.savepoints.clear();
+            false
+        }))?;
+
+        let hook_registry = registry.clone();
+        conn.rollback_hook(Some(move || {
+            let mut registry = lock(&hook_registry);
+            registry.pending.clear();
+            registry.savepoints.clear();
+            registry.committing.clear();
+        }))?;
+
+        let hook_registry = registry.clone();
+        conn.authorizer(Some(move |context: AuthContext<'_>| {
+            lock(&hook_registry).prepare(context.action);
+            Authorization::Allow
+        }))?;
+
+        Ok(Self { registry })
+    }
+
+    /// Removes the hooks from the connection. Committed changes are kept.
+    pub fn uninstall(&self, conn: &Connection) -> Result<()> {
+        conn.update_hook(None::<fn(Action, &str, &str, i64)>)?;
+        conn.commit_hook(None::<fn() -> bool>)?;
+        conn.rollback_hook(None::<fn()>)?;
+        conn.authorizer(None::<fn(AuthContext<'_>) -> Authorization>)?;
+        let mut registry = lock(&self.registry);
+        registry.pending.clear();
+        registry.savepoints.clear();
+        if conn.is_autocommit() {
+            registry.committed();
+        } else {
+            registry.committing.clear();
+        }
+        Ok(())
+    }
+
+    /// Calls `callback` with every committed insert, update and delete of `T`.
+    pub fn subscribe<T: DbTable>(
+        &self,
+        callback: impl FnMut(&ChangeEvent) + Send + 'static,
+    ) -> SubscriptionId
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        let mut registry = lock(&self.registry);
+        let id = registry.next_id();
+        registry
+            .subscribers
+            .entry(T::TABLE_NAME.to_string())
+            .or_default()
+            .push((id, Box::new(callback)));
+        id
+    }
+
+    /// Calls `callback` with every committed change of `T` and the row as it is when
+    /// [`Subscriptions::deliver`] loads it, `None` for deleted rows and ones `select` doesn't
+    /// return.
+    pub fn subscribe_rows<T: DbTable + 'static>(
+        &self,
+        mut callback: impl FnMut(&ChangeEvent, Option<&T>) + Send + 'static,
+    ) -> SubscriptionId
+    where
+        rusqlite::Error: for<'a> From<<T as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+    {
+        let load = move |conn: &Connection, event: &ChangeEvent| {
+            let row = match event.kind {
+                ChangeKind::Delete => None,
+                _ => T::select_one(conn, "WHERE ROWID = ?1", [event.rowid])?,
+            };
+            callback(event, row.as_ref());
+            Ok(())
+        };
+        let mut registry = lock(&self.registry);
+        let id = registry.next_id();
+        registry
+            .row_subscribers
+            .entry(T::TABLE_NAME.to_string())
+            .or_default()
+            .push((id, Box::new(load)));
+        id
+    }
+
+    /// Returns whether the subscription existed.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut registry = lock(&self.registry);
+        let Registry {
+            committed,
+            subscribers,
+            row_subscribers,
+            delivering,
+            unsubscribed,
+            ..
+        } = &mut *registry;
+        if remove(subscribers, id) || remove(row_subscribers, id) {
+            if delivering.is_empty() {
+                committed.retain(|(table, _)| {
+                    subscribers.contains_key(table) || row_subscribers.contains_key(table)
+                });
+            }
+            return true;
+        }
+        // Taken out by the running `deliver`, which drops it afterwards
+        let taken = delivering.iter().any(|(_, d)| *d == id) && !unsubscribed.contains(&id);
+        if taken {
+            unsubscribed.push(id);
+        }
+        taken
+    }
+
+    /// Passes the changes committed since the last call to the callbacks of their table, in
+    /// commit order, loading the rows for the [`Subscriptions::subscribe_rows`] ones. Returns
+    /// the number of changes delivered.
+    ///
+    /// Call it outside of transactions: changes whose commit hasn't finished wait for the next
+    /// call. The callbacks may use the connection and the subscriptions.
+    pub fn deliver(&self, conn: &Connection) -> Result<usize> {
+        // Callbacks may run statements, which call the hooks, or subscribe and unsubscribe, so
+        // the lock isn't held while they run
+        let (committed, mut subscribers, mut row_subscribers) = {
+            let mut registry = lock(&self.registry);
+            if conn.is_autocommit() {
+                registry.committed();
+            }
+            let committed = std::mem::take(&mut registry.committed);
+            let subscribers = std::mem::take(&mut registry.subscribers);
+            let row_subscribers = std::mem::take(&mut registry.row_subscribers);
+            registry.delivering = ids(&subscribers).chain(ids(&row_subscribers)).collect();
+            (committed, subscribers, row_subscribers)
+        };
+        let mut result = Ok(committed.len());
+        for (table, event) in committed.iter() {
+            for (_, callback) in subscribers.get_mut(table).into_iter().flatten() {
+                callback(event);
+            }
+            for (_, load) in row_subscribers.get_mut(table).into_iter().flatten() {
+                if let Err(err) = load(conn, event) {
+                    result = Err(err);
+                }
+            }
+        }
+        let mut registry = lock(&self.registry);
+        let unsubscribed = std::mem::take(&mut registry.unsubscribed);
+        registry.delivering.clear();
+        for id in unsubscribed {
+            remove(&mut subscribers, id);
+            remove(&mut row_subscribers, id);
+        }
+        for (table, mut added) in std::mem::take(&mut registry.subscribers) {
+            subscribers.entry(table).or_default().append(&mut added);
+        }
+        for (table, mut added) in std::mem::take(&mut registry.row_subscribers) {
+            row_subscribers.entry(table).or_default().append(&mut added);
+        }
+        registry.subscribers = subscribers;
+        registry.row_subscribers = row_subscribers;
+        result
+    }
+}
+
+fn ids<C>(
+    subscribers: &HashMap<String, Vec<(SubscriptionId, C)>>,
+) -> impl Iterator<Item = (String, SubscriptionId)> + '_ {
+    subscribers
+        .iter()
+        .flat_map(|(table, callbacks)| callbacks.iter().map(|(id, _)| (table.clone(), *id)))
+}
+
+fn remove<C>(
+    subscribers: &mut HashMap<String, Vec<(SubscriptionId, C)>>,
+    id: SubscriptionId,
+) -> bool {
+    let mut removed = false;
+    for callbacks in subscribers.values_mut() {
+        let len = callbacks.len();
+        callbacks.retain(|(sub, _)| *sub != id);
+        removed |= callbacks.len() < len;
+    }
+    subscribers.retain(|_, callbacks| !callbacks.is_empty());
+    removed
+}
+
+/// A callback panicking poisons the lock without breaking the registry.
+fn lock(registry: &Mutex<Registry>) -> MutexGuard<'_, Registry> {
+    registry.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -1,11 +1,31 @@
-use rusqlite::{OptionalExtension, Result};
+use rusqlite::{
+    OptionalExtension,
+    types::{ToSqlOutput, Value},
+};
+
+use crate::{DefaultKind, QueryPlan, Result, ResultKind, Timestamp};
 
 pub trait DbTable: Sized + for<'a> TryFrom<&'a rusqlite::Row<'a>>
 where
     rusqlite::Error: for<'a> From<<Self as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
 {
+    /// The insert builder with every required field set, the one `build*` exists on.
+    type Builder;
     const TABLE_NAME: &'static str;
+    /// The tables this one has `#[foreign_key]`s to.
+    const FOREIGN_TABLES: &'static [&'static str] = &[];
+    /// The fields with a `#[foreign_key]`.
+    const FOREIGN_KEY_FIELDS: &'static [&'static str] = &[];
+    /// Whether it's a `#[table(without_rowid)]` table.
+    const WITHOUT_ROWID: bool = false;
+    /// What [`DbTable::select`] reads from, the table unless `#[soft_delete]` hides rows.
+    const SELECT_FROM: &'static str = Self::TABLE_NAME;
     fn create_table_str() -> String;
+    /// The statements run after [`DbTable::create_table_str`] for the `#[updated_at(trigger)]`
+    /// triggers and the `#[history]` table, separated by `;`. Empty for other tables.
+    fn create_triggers_str() -> String {
+        String::new()
+    }
     fn column_names() -> Box<[&'static str]>;
     fn column_getters() -> String {
         Self::column_names().join(",")
@@ -13,7 +33,9 @@ where
     /// Create the table in the database.
     fn create_table(conn: &rusqlite::Connection) -> Result<usize> {
         let sql = Self::create_table_str();
-        conn.execute(&sql, ())
+        let created = conn.execute(&sql, ())?;
+        conn.execute_batch(&Self::create_triggers_str())?;
+        Ok(created)
     }
 
     /// Selects all rows from the table for which the where clause is true.
@@ -21,11 +43,11 @@ where
         conn: &rusqlite::Connection,
         where_clause: &str,
         params: impl rusqlite::Params,
-    ) -> rusqlite::Result<Box<[Self]>> {
+    ) -> Result<Box<[Self]>> {
         let sql = format!(
             "SELECT {} FROM {} {}",
             Self::column_getters(),
-            Self::TABLE_NAME,
+            Self::SELECT_FROM,
             where_clause
         );
         let mut stmt = conn.prepare(&sql)?;
@@ -39,11 +61,11 @@ where
         conn: &rusqlite::Connection,
         where_clause: &str,
         params: impl rusqlite::Params,
-    ) -> rusqlite::Result<Option<Self>> {
+    ) -> Result<Option<Self>> {
         let sql = format!(
             "SELECT {} FROM {} {} LIMIT 1",
             Self::column_getters(),
-            Self::TABLE_NAME,
+            Self::SELECT_FROM,
             where_clause
         );
         let mut stmt = conn.prepare(&sql)?;
@@ -53,30 +75,605 @@ where
         Ok(row)
     }
 
+    /// Deletes the rows for which the where clause is true, with constraint failures such as
+    /// `ON DELETE RESTRICT` classified by [`crate::Error`].
     fn delete(
         conn: &rusqlite::Connection,
         where_clause: &str,
         params: impl rusqlite::Params,
-    ) -> rusqlite::Result<usize> {
+    ) -> Result<usize> {
         let sql = format!("DELETE FROM {} {}", Self::TABLE_NAME, where_clause);
-        let mut stmt = conn.prepare(&sql).unwrap();
+        let mut stmt = conn.prepare(&sql)?;
         stmt.execute(params)
+            .map_err(crate::Error::for_table::<Self>)
     }
 
-    fn drop_table(conn: &rusqlite::Connection) -> rusqlite::Result<usize> {
+    /// The query plan of [`DbTable::select`] with the same arguments.
+    fn explain_select(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<QueryPlan> {
+        let sql = format!(
+            "SELECT {} FROM {} {}",
+            Self::column_getters(),
+            Self::SELECT_FROM,
+            where_clause
+        );
+        QueryPlan::explain(conn, &sql, params)
+    }
+
+    /// The query plan of [`DbTable::delete`] with the same arguments. For `#[soft_delete]` tables
+    /// it's the plan of the `UPDATE` setting the deletion time. `#[hooks]` tables explain the
+    /// [`DbTable::select`] finding the rows, which are then deleted one at a time by key.
+    fn explain_delete(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<QueryPlan> {
+        let sql = format!("DELETE FROM {} {}", Self::TABLE_NAME, where_clause);
+        QueryPlan::explain(conn, &sql, params)
+    }
+
+    fn drop_table(conn: &rusqlite::Connection) -> Result<usize> {
         let sql = format!("DROP TABLE IF EXISTS {}", Self::TABLE_NAME);
-        conn.execute(&sql, ())
+        Ok(conn.execute(&sql, ())?)
+    }
+}
+
+/// Tables with a `#[soft_delete]` field, an `Option` of a [`Timestamp`] set when the row is
+/// deleted. [`DbTable::delete`] sets it instead of removing rows, and [`DbTable::select`] and
+/// [`DbTable::select_one`] skip rows where it's set.
+///
+/// The where clauses of the methods here work like the ones of [`DbTable`].
+pub trait SoftDelete: DbTable
+where
+    rusqlite::Error: for<'a> From<<Self as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+{
+    /// The type of the `#[soft_delete]` field, without the `Option`.
+    type DeletedAt: Timestamp + rusqlite::ToSql;
+    /// The `#[soft_delete]` column.
+    const DELETED_AT: &'static str;
+    /// The columns identifying a row, `ROWID` unless it's a `WITHOUT ROWID` table.
+    const ROW_KEY: &'static str;
+
+    /// Sets the deletion time of the rows for which the where clause is true, which
+    /// [`DbTable::delete`] does.
+    fn soft_delete(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<usize> {
+        let sql = Self::soft_delete_str(where_clause)?;
+        let mut stmt = conn.prepare(&sql)?;
+        stmt.execute(params)
+            .map_err(crate::Error::for_table::<Self>)
+    }
+
+    #[doc(hidden)]
+    fn soft_delete_str(where_clause: &str) -> Result<String> {
+        Ok(format!(
+            "UPDATE {} SET {} = {} WHERE ({}) IN (SELECT {} FROM {} {})",
+            Self::TABLE_NAME,
+            Self::DELETED_AT,
+            sql_literal(Self::DeletedAt::now())?,
+            Self::ROW_KEY,
+            Self::ROW_KEY,
+            Self::SELECT_FROM,
+            where_clause
+        ))
+    }
+
+    /// [`DbTable::select`] including the deleted rows.
+    fn with_deleted(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Box<[Self]>> {
+        let sql = format!(
+            "SELECT {} FROM {} {}",
+            Self::column_getters(),
+            Self::TABLE_NAME,
+            where_clause
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params, |row| Ok(Self::try_from(row)?))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows)
+    }
+
+    /// [`DbTable::select`] of only the deleted rows.
+    fn only_deleted(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Box<[Self]>> {
+        let sql = format!(
+            "SELECT {} FROM {} {}",
+            Self::column_getters(),
+            Self::deleted_rows(),
+            where_clause
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params, |row| Ok(Self::try_from(row)?))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows)
+    }
+
+    /// Clears the deletion time of the deleted rows for which the where clause is true.
+    fn restore(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<usize> {
+        let sql = format!(
+            "UPDATE {} SET {} = NULL WHERE ({}) IN (SELECT {} FROM {} {})",
+            Self::TABLE_NAME,
+            Self::DELETED_AT,
+            Self::ROW_KEY,
+            Self::ROW_KEY,
+            Self::deleted_rows(),
+            where_clause
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        stmt.execute(params)
+            .map_err(crate::Error::for_table::<Self>)
+    }
+
+    /// Removes the rows for which the where clause is true, deleted or not. Doesn't run the
+    /// `before_delete` and `after_delete` hooks.
+    fn purge(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<usize> {
+        let sql = format!("DELETE FROM {} {}", Self::TABLE_NAME, where_clause);
+        let mut stmt = conn.prepare(&sql)?;
+        stmt.execute(params)
+            .map_err(crate::Error::for_table::<Self>)
+    }
+
+    /// The deleted rows as a subquery named like the table, keeping `ROWID` available.
+    fn deleted_rows() -> String {
+        let rowid = if Self::ROW_KEY == "ROWID" {
+            "ROWID AS ROWID, "
+        } else {
+            ""
+        };
+        format!(
+            "(SELECT {rowid}* FROM {0} WHERE {1} IS NOT NULL) AS {0}",
+            Self::TABLE_NAME,
+            Self::DELETED_AT
+        )
+    }
+}
+
+/// The value as an SQL literal, for statements whose parameters are all taken by the caller.
+fn sql_literal(value: impl rusqlite::ToSql) -> rusqlite::Result<String> {
+    let value = match value.to_sql()? {
+        ToSqlOutput::Borrowed(value) => Value::try_from(value)
+            .map_err(|err| rusqlite::Error::ToSqlConversionFailure(Box::new(err)))?,
+        ToSqlOutput::Owned(value) => value,
+        _ => return Err(rusqlite::Error::InvalidQuery),
+    };
+    let literal = match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(i) => i.to_string(),
+        Value::Real(r) => format!("{r:?}"),
+        Value::Text(text) => format!("'{}'", text.replace('\'', "''")),
+        Value::Blob(blob) => {
+            let hex = blob.iter().map(|b| format!("{b:02X}")).collect::<String>();
+            format!("X'{hex}'")
+        }
+    };
+    Ok(literal)
+}
+
+/// Logic run around the generated writes of a table deriving `DbTable` with `#[hooks]`. Every
+/// hook does nothing unless overridden, and an error from one aborts the write it belongs to.
+///
+/// Inserts through the builder run `before_insert` before the `#[validate(...)]` rules are
+/// checked and `after_insert` with the inserted row. `update` runs `before_update` and
+/// `after_update`. [`DbTable::delete`] selects the matching rows first and deletes them one at a
+/// time by key within a savepoint, running `before_delete` before each. An error from one rolls
+/// back every row deleted so far. Once the savepoint is released `after_delete` runs for every
+/// deleted row.
+///
+/// The `after_*` hooks run once the statement succeeded, so errors from them only undo it when
+/// the write is part of a transaction.
+///
+/// ```ignore
+/// #[derive(DbTable)]
+/// #[hooks]
+/// struct User { ... }
+///
+/// impl DbTableHooks for User {
+///     fn before_insert(builder: &mut UserBuilder<FieldSet, FieldSet>, _: &Connection) -> typed_db::Result<()> {
+///         builder.email = builder.email.take().map(|email| email.to_lowercase());
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait DbTableHooks: DbTable
+where
+    rusqlite::Error: for<'a> From<<Self as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+{
+    fn before_insert(_builder: &mut Self::Builder, _conn: &rusqlite::Connection) -> Result<()> {
+        Ok(())
+    }
+
+    fn after_insert(&self, _conn: &rusqlite::Connection) -> Result<()> {
+        Ok(())
+    }
+
+    fn before_update(&mut self, _conn: &rusqlite::Connection) -> Result<()> {
+        Ok(())
+    }
+
+    fn after_update(&self, _conn: &rusqlite::Connection) -> Result<()> {
+        Ok(())
+    }
+
+    fn before_delete(&self, _conn: &rusqlite::Connection) -> Result<()> {
+        Ok(())
+    }
+
+    fn after_delete(&self, _conn: &rusqlite::Connection) -> Result<()> {
+        Ok(())
     }
 }
 
 pub trait DbType: Default {
+    /// Unsigned types get a `CHECK (column >= 0)` constraint.
+    const UNSIGNED: bool = false;
+    /// The `#[default(...)]` values this type accepts, or `None` to accept any.
+    const DEFAULTS: Option<&'static [DefaultKind]> = None;
+    /// The storage classes this type can be read from, or `None` to accept any.
+    const RESULT_KINDS: Option<&'static [ResultKind]> = None;
+    /// Whether the type is declared `INTEGER`, so a `#[primary_key]` of it aliases the rowid and
+    /// inserts may leave it for SQLite to assign.
+    const ROWID_ALIAS: bool = false;
     fn db_type() -> &'static str;
+    /// The column type used in `#[table(strict)]` tables. One of `INTEGER`, `REAL`, `TEXT`, `BLOB`
+    /// or `ANY`, picked from [`DbType::db_type`] using SQLite's
+    /// [affinity rules](https://www.sqlite.org/datatype3.html#determination_of_column_affinity).
+    fn strict_db_type() -> &'static str {
+        let db_type = Self::db_type().to_uppercase();
+        if db_type.contains("INT") {
+            "INTEGER"
+        } else if ["CHAR", "CLOB", "TEXT"].iter().any(|t| db_type.contains(t)) {
+            "TEXT"
+        } else if db_type.contains("BLOB") || db_type.is_empty() {
+            "BLOB"
+        } else if ["REAL", "FLOA", "DOUB"].iter().any(|t| db_type.contains(t)) {
+            "REAL"
+        } else {
+            "ANY"
+        }
+    }
 }
 
 pub trait CommonTableExpression: Sized {
+    /// The `<Name>Params` struct of `#[cte_params(...)]`, or `()` for CTEs without parameters.
+    /// Untyped `"name"` params are `rusqlite::types::Value` fields.
+    type Params: CteParams;
     fn cte_str() -> &'static str;
-    fn select(
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self>;
+
+    /// Runs the CTE, binding the typed parameters by name.
+    fn select(conn: &rusqlite::Connection, params: Self::Params) -> Result<Box<[Self]>> {
+        Self::select_raw(conn, params.named_params().as_slice())
+    }
+
+    /// Runs the CTE with parameters bound in the order they are declared in `#[cte_params(...)]`.
+    fn select_raw(
         conn: &rusqlite::Connection,
         params: impl rusqlite::Params,
-    ) -> rusqlite::Result<Box<[Self]>>;
+    ) -> Result<Box<[Self]>> {
+        let mut stmt = conn.prepare(Self::cte_str())?;
+        let rows = stmt
+            .query_map(params, |row| Self::from_row(row))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows)
+    }
+
+    /// The query plan of [`CommonTableExpression::select`] with the same arguments.
+    fn explain(conn: &rusqlite::Connection, params: Self::Params) -> Result<QueryPlan> {
+        Self::explain_raw(conn, params.named_params().as_slice())
+    }
+
+    /// The query plan of [`CommonTableExpression::select_raw`] with the same arguments.
+    fn explain_raw(
+        conn: &rusqlite::Connection,
+        params: impl rusqlite::Params,
+    ) -> Result<QueryPlan> {
+        QueryPlan::explain(conn, Self::cte_str(), params)
+    }
+
+    /// The CTE with `params` inlined as literals, a param-free variant that can be stored as a
+    /// view.
+    fn param_free_str(params: &Self::Params) -> Result<String> {
+        // The `params` table comes first, so its placeholders are the first occurrences
+        let mut sql = Self::cte_str().trim_end_matches(';').to_string();
+        for (placeholder, value) in params.named_params() {
+            let column = format!("{placeholder} AS {}", &placeholder[1..]);
+            let literal = format!("{} AS {}", sql_literal(value)?, &placeholder[1..]);
+            sql = sql.replacen(&column, &literal, 1);
+        }
+        Ok(sql)
+    }
+
+    /// Creates the param-free variant of the CTE for `params` as the view `view_name`, e.g. one
+    /// view per tenant.
+    fn create_view_as(
+        conn: &rusqlite::Connection,
+        view_name: &str,
+        params: &Self::Params,
+    ) -> Result<usize> {
+        let sql = format!(
+            "CREATE VIEW IF NOT EXISTS {view_name} AS {}",
+            Self::param_free_str(params)?
+        );
+        Ok(conn.execute(&sql, ())?)
+    }
+
+    /// Selects all rows from the view `view_name` created by
+    /// [`CommonTableExpression::create_view_as`] for which the where clause is true.
+    fn select_view_as(
+        conn: &rusqlite::Connection,
+        view_name: &str,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Box<[Self]>> {
+        let sql = format!("SELECT * FROM {view_name} {where_clause}");
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt
+            .query_map(params, |row| Self::from_row(row))?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(rows)
+    }
+}
+
+/// A [`CommonTableExpression`] without parameters persisted as an SQL view, so the sqlite3 shell
+/// and other tools can query the same logic. CTEs with parameters are stored for given values with
+/// [`CommonTableExpression::create_view_as`].
+pub trait DbView: CommonTableExpression<Params = ()> {
+    const VIEW_NAME: &'static str;
+
+    fn create_view_str() -> String {
+        format!(
+            "CREATE VIEW IF NOT EXISTS {} AS {}",
+            Self::VIEW_NAME,
+            Self::cte_str().trim_end_matches(';')
+        )
+    }
+
+    /// Create the view in the database.
+    fn create_view(conn: &rusqlite::Connection) -> Result<usize> {
+        Ok(conn.execute(&Self::create_view_str(), ())?)
+    }
+
+    fn drop_view(conn: &rusqlite::Connection) -> Result<usize> {
+        let sql = format!("DROP VIEW IF EXISTS {}", Self::VIEW_NAME);
+        Ok(conn.execute(&sql, ())?)
+    }
+
+    /// Selects all rows from the view for which the where clause is true.
+    fn select_view(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Box<[Self]>> {
+        Self::select_view_as(conn, Self::VIEW_NAME, where_clause, params)
+    }
+
+    fn select_one_view(
+        conn: &rusqlite::Connection,
+        where_clause: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Option<Self>> {
+        let sql = format!("SELECT * FROM {} {} LIMIT 1", Self::VIEW_NAME, where_clause);
+        let mut stmt = conn.prepare(&sql)?;
+        let row = stmt
+            .query_row(params, |row| Self::from_row(row))
+            .optional()?;
+        Ok(row)
+    }
+}
+
+/// Rows of the content table, each with a value computed by the FTS table.
+type Matches<T, V> = Box<[(T, V)]>;
+
+/// An external-content FTS5 table indexing columns of a [`DbTable`], kept in sync by triggers on
+/// it. The content table needs a rowid, which the derive checks, the FTS table stores only the
+/// index.
+///
+/// ```ignore
+/// #[derive(DbFtsTable)]
+/// #[fts5(content = User, columns(name, email))]
+/// struct UserSearch;
+///
+/// let conn = Database::open_in_memory()
+///     .table::<User>()
+///     .fts_table::<UserSearch>()
+///     .connect()?;
+/// for (user, rank) in UserSearch::search(&conn, "alice OR bob")? { .. }
+/// ```
+pub trait DbFtsTable
+where
+    rusqlite::Error: for<'a> From<<Self::Content as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+{
+    type Content: DbTable;
+    const TABLE_NAME: &'static str;
+    /// The indexed columns of the content table.
+    const COLUMNS: &'static [&'static str];
+    /// Further `fts5(..)` arguments, like `, tokenize = 'porter'`.
+    const OPTIONS: &'static str = "";
+
+    /// The `CREATE VIRTUAL TABLE` statement followed by the insert, delete and update triggers
+    /// on the content table.
+    fn create_table_str() -> String {
+        let name = Self::TABLE_NAME;
+        let content = Self::Content::TABLE_NAME;
+        let columns = Self::COLUMNS.join(", ");
+        let values = |row: &str| {
+            Self::COLUMNS
+                .iter()
+                .map(|c| format!("{row}.{c}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let (new, old) = (values("new"), values("old"));
+        let insert = format!("INSERT INTO {name} (rowid, {columns}) VALUES (new.rowid, {new});");
+        let delete = format!(
+            "INSERT INTO {name} ({name}, rowid, {columns}) VALUES ('delete', old.rowid, {old});"
+        );
+        let trigger = |operation: &str, event: &str, body: &str| {
+            format!(
+                "CREATE TRIGGER IF NOT EXISTS {name}_{operation} AFTER {event} ON {content} BEGIN {body} END"
+            )
+        };
+        [
+            format!(
+                "CREATE VIRTUAL TABLE IF NOT EXISTS {name} USING fts5({columns}, content = '{content}'{})",
+                Self::OPTIONS
+            ),
+            trigger("insert", "INSERT", &insert),
+            trigger("delete", "DELETE", &delete),
+            // Only changes to the indexed columns need reindexing
+            trigger(
+                "update",
+                &format!("UPDATE OF {columns}"),
+                &format!("{delete} {insert}"),
+            ),
+        ]
+        .join(";\n")
+    }
+
+    /// Create the table and its triggers in the database. Rows the content table already has
+    /// aren't indexed until [`DbFtsTable::rebuild`].
+    fn create_table(conn: &rusqlite::Connection) -> Result<usize> {
+        conn.execute_batch(&Self::create_table_str())?;
+        Ok(0)
+    }
+
+    /// Drops the table along with the triggers on the content table.
+    fn drop_table(conn: &rusqlite::Connection) -> Result<usize> {
+        let name = Self::TABLE_NAME;
+        conn.execute_batch(&format!(
+            "DROP TRIGGER IF EXISTS {name}_insert;
+DROP TRIGGER IF EXISTS {name}_delete;
+DROP TRIGGER IF EXISTS {name}_update;
+DROP TABLE IF EXISTS {name};"
+        ))?;
+        Ok(0)
+    }
+
+    /// Reindexes every row of the content table.
+    fn rebuild(conn: &rusqlite::Connection) -> Result<usize> {
+        let name = Self::TABLE_NAME;
+        Ok(conn.execute(
+            &format!("INSERT INTO {name} ({name}) VALUES ('rebuild')"),
+            [],
+        )?)
+    }
+
+    /// The rows matching the FTS5 query, best first, with their `rank`. Lower ranks are better
+    /// matches.
+    fn search(conn: &rusqlite::Connection, query: &str) -> Result<Matches<Self::Content, f64>> {
+        let rank = format!("{}.rank", Self::TABLE_NAME);
+        fts_query::<Self, _>(conn, &rank, &[&query])
+    }
+
+    /// [`DbFtsTable::search`] with the value of `column`, its matches between `open` and
+    /// `close`.
+    fn highlight(
+        conn: &rusqlite::Connection,
+        query: &str,
+        column: &str,
+        open: &str,
+        close: &str,
+    ) -> Result<Matches<Self::Content, String>> {
+        let expr = format!(
+            "highlight({}, {}, ?2, ?3)",
+            Self::TABLE_NAME,
+            fts_column::<Self>(column)?
+        );
+        fts_query::<Self, _>(conn, &expr, &[&query, &open, &close])
+    }
+
+    /// [`DbFtsTable::highlight`] of the part of `column` with the most matches, at most
+    /// `max_tokens` long and with `ellipsis` where it's cut.
+    fn snippet(
+        conn: &rusqlite::Connection,
+        query: &str,
+        column: &str,
+        open: &str,
+        close: &str,
+        ellipsis: &str,
+        max_tokens: u8,
+    ) -> Result<Matches<Self::Content, String>> {
+        let expr = format!(
+            "snippet({}, {}, ?2, ?3, ?4, ?5)",
+            Self::TABLE_NAME,
+            fts_column::<Self>(column)?
+        );
+        let params: [&dyn rusqlite::ToSql; 5] = [&query, &open, &close, &ellipsis, &max_tokens];
+        fts_query::<Self, _>(conn, &expr, &params)
+    }
+}
+
+/// The index of `column` among the indexed columns.
+fn fts_column<F: DbFtsTable + ?Sized>(column: &str) -> rusqlite::Result<usize>
+where
+    rusqlite::Error: for<'a> From<<F::Content as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+{
+    F::COLUMNS
+        .iter()
+        .position(|c| *c == column)
+        .ok_or_else(|| rusqlite::Error::InvalidColumnName(column.to_string()))
+}
+
+/// The content rows matching `?1` with the value of `expr`, best first.
+fn fts_query<F: DbFtsTable + ?Sized, T: rusqlite::types::FromSql>(
+    conn: &rusqlite::Connection,
+    expr: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> Result<Matches<F::Content, T>>
+where
+    rusqlite::Error: for<'a> From<<F::Content as TryFrom<&'a rusqlite::Row<'a>>>::Error>,
+{
+    let name = F::TABLE_NAME;
+    let content = F::Content::TABLE_NAME;
+    let columns = F::Content::column_names();
+    let sql = format!(
+        "SELECT {}, {expr} FROM {name} JOIN {} ON {content}.ROWID = {name}.rowid \
+        WHERE {name} MATCH ?1 ORDER BY {name}.rank",
+        columns
+            .iter()
+            .map(|c| format!("{content}.{c}"))
+            .collect::<Vec<_>>()
+            .join(", "),
+        F::Content::SELECT_FROM,
+    );
+    let mut stmt = conn.prepare(&sql)?;
+    let rows = stmt
+        .query_map(params, |row| {
+            Ok((F::Content::try_from(row)?, row.get(columns.len())?))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(rows)
+}
+
+/// Parameters of a [`CommonTableExpression`], bound by name.
+pub trait CteParams {
+    fn named_params(&self) -> Vec<(&'static str, &dyn rusqlite::ToSql)>;
+}
+
+impl CteParams for () {
+    fn named_params(&self) -> Vec<(&'static str, &dyn rusqlite::ToSql)> {
+        Vec::new()
+    }
 }
@@ -1,36 +1,330 @@
-use std::ffi::OsString;
+use std::{ffi::OsString, fmt::Display, num::TryFromIntError, str::FromStr};
 
 use chrono::prelude::*;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
 
 use crate::DbType;
 
 #[macro_export]
 macro_rules! impl_db_type {
-    ($($ty:ty) *, $db_type:expr) => {
-        $(
-        impl DbType for $ty {
-            fn db_type() -> &'static str {
-                $db_type
-            }
-        })*
+    (@kinds) => {
+        None
+    };
+    (@kinds $kinds:expr) => {
+        Some(&$kinds)
+    };
+    (unsigned $($ty:ty) *, $db_type:expr $(, defaults = $defaults:expr)? $(, results = $results:expr)?) => {
+        const _: () = {
+            const DEFAULTS: Option<&[$crate::DefaultKind]> = $crate::impl_db_type!(@kinds $($defaults)?);
+            const RESULT_KINDS: Option<&[$crate::ResultKind]> = $crate::impl_db_type!(@kinds $($results)?);
+            $(
+            impl DbType for $ty {
+                const UNSIGNED: bool = true;
+                const DEFAULTS: Option<&'static [$crate::DefaultKind]> = DEFAULTS;
+                const RESULT_KINDS: Option<&'static [$crate::ResultKind]> = RESULT_KINDS;
+                const ROWID_ALIAS: bool = matches!($db_type.as_bytes(), b"INTEGER");
+                fn db_type() -> &'static str {
+                    $db_type
+                }
+            })*
+        };
+    };
+    ($($ty:ty) *, $db_type:expr, strict = $strict_type:expr $(, defaults = $defaults:expr)? $(, results = $results:expr)?) => {
+        const _: () = {
+            const DEFAULTS: Option<&[$crate::DefaultKind]> = $crate::impl_db_type!(@kinds $($defaults)?);
+            const RESULT_KINDS: Option<&[$crate::ResultKind]> = $crate::impl_db_type!(@kinds $($results)?);
+            $(
+            impl DbType for $ty {
+                const DEFAULTS: Option<&'static [$crate::DefaultKind]> = DEFAULTS;
+                const RESULT_KINDS: Option<&'static [$crate::ResultKind]> = RESULT_KINDS;
+                const ROWID_ALIAS: bool = matches!($db_type.as_bytes(), b"INTEGER");
+                fn db_type() -> &'static str {
+                    $db_type
+                }
+                fn strict_db_type() -> &'static str {
+                    $strict_type
+                }
+            })*
+        };
+    };
+    ($($ty:ty) *, $db_type:expr $(, defaults = $defaults:expr)? $(, results = $results:expr)?) => {
+        const _: () = {
+            const DEFAULTS: Option<&[$crate::DefaultKind]> = $crate::impl_db_type!(@kinds $($defaults)?);
+            const RESULT_KINDS: Option<&[$crate::ResultKind]> = $crate::impl_db_type!(@kinds $($results)?);
+            $(
+            impl DbType for $ty {
+                const DEFAULTS: Option<&'static [$crate::DefaultKind]> = DEFAULTS;
+                const RESULT_KINDS: Option<&'static [$crate::ResultKind]> = RESULT_KINDS;
+                const ROWID_ALIAS: bool = matches!($db_type.as_bytes(), b"INTEGER");
+                fn db_type() -> &'static str {
+                    $db_type
+                }
+            })*
+        };
     };
 }
 
-impl_db_type!(i8 u8, "TINYINT");
-impl_db_type!(i16 u16, "SMALLINT");
-impl_db_type!(i32 u32 isize usize, "INTEGER");
-impl_db_type!(i64, "BIGINT");
-impl_db_type!(u64, "UNSIGNED BIG INT");
-impl_db_type!(&str String std::path::PathBuf OsString, "TEXT");
-impl_db_type!(f32, "FLOAT");
-impl_db_type!(f64, "DOUBLE");
-impl_db_type!(bool, "BOOLEAN");
-impl_db_type!(NaiveDate, "DATE");
-impl_db_type!(NaiveDateTime DateTime<Utc>, "DATETIME");
-impl_db_type!(Vec<u8> &[u8], "BLOB");
+use DefaultKind::*;
+
+const INTEGER_DEFAULTS: [DefaultKind; 2] = [Integer, Boolean];
+const TEXT_DEFAULTS: [DefaultKind; 4] = [Text, CurrentTimestamp, CurrentDate, CurrentTime];
+
+const INTEGER_RESULTS: [ResultKind; 1] = [ResultKind::Integer];
+const REAL_RESULTS: [ResultKind; 2] = [ResultKind::Integer, ResultKind::Real];
+const TEXT_RESULTS: [ResultKind; 1] = [ResultKind::Text];
+
+impl_db_type!(
+    i8,
+    "TINYINT",
+    defaults = INTEGER_DEFAULTS,
+    results = INTEGER_RESULTS
+);
+impl_db_type!(unsigned u8, "TINYINT", defaults = INTEGER_DEFAULTS, results = INTEGER_RESULTS);
+impl_db_type!(
+    i16,
+    "SMALLINT",
+    defaults = INTEGER_DEFAULTS,
+    results = INTEGER_RESULTS
+);
+impl_db_type!(unsigned u16, "SMALLINT", defaults = INTEGER_DEFAULTS, results = INTEGER_RESULTS);
+impl_db_type!(i32 isize, "INTEGER", defaults = INTEGER_DEFAULTS, results = INTEGER_RESULTS);
+impl_db_type!(unsigned u32 usize, "INTEGER", defaults = INTEGER_DEFAULTS, results = INTEGER_RESULTS);
+impl_db_type!(
+    i64,
+    "BIGINT",
+    defaults = INTEGER_DEFAULTS,
+    results = INTEGER_RESULTS
+);
+impl_db_type!(unsigned u64, "UNSIGNED BIG INT", defaults = INTEGER_DEFAULTS, results = INTEGER_RESULTS);
+impl_db_type!(&str String std::path::PathBuf OsString, "TEXT", defaults = TEXT_DEFAULTS, results = TEXT_RESULTS);
+impl_db_type!(
+    f32,
+    "FLOAT",
+    defaults = [Integer, Real],
+    results = REAL_RESULTS
+);
+impl_db_type!(
+    f64,
+    "DOUBLE",
+    defaults = [Integer, Real],
+    results = REAL_RESULTS
+);
+impl_db_type!(
+    bool,
+    "BOOLEAN",
+    strict = "INTEGER",
+    defaults = [Boolean, Integer],
+    results = INTEGER_RESULTS
+);
+impl_db_type!(
+    NaiveDate,
+    "DATE",
+    strict = "TEXT",
+    defaults = [Text, CurrentDate],
+    results = TEXT_RESULTS
+);
+impl_db_type!(
+    NaiveDateTime,
+    "DATETIME",
+    strict = "TEXT",
+    defaults = [Text, CurrentTimestamp],
+    results = TEXT_RESULTS
+);
+// rusqlite also reads it from unix timestamps
+impl_db_type!(
+    DateTime<Utc>,
+    "DATETIME",
+    strict = "TEXT",
+    defaults = [Text, CurrentTimestamp],
+    results = [ResultKind::Integer, ResultKind::Text]
+);
+impl_db_type!(Vec<u8> &[u8], "BLOB", defaults = [Blob], results = [ResultKind::Blob]);
 
 impl<T: DbType> DbType for Option<T> {
+    const UNSIGNED: bool = T::UNSIGNED;
+    const DEFAULTS: Option<&'static [DefaultKind]> = T::DEFAULTS;
+    const RESULT_KINDS: Option<&'static [ResultKind]> = T::RESULT_KINDS;
+    const ROWID_ALIAS: bool = T::ROWID_ALIAS;
     fn db_type() -> &'static str {
         T::db_type()
     }
+    fn strict_db_type() -> &'static str {
+        T::strict_db_type()
+    }
+}
+
+/// The kinds of SQL literal a `#[default(...)]` attribute can hold. Defaults are checked at
+/// compile time against the field's [`DbType::DEFAULTS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultKind {
+    Integer,
+    Real,
+    Boolean,
+    Text,
+    Blob,
+    CurrentTimestamp,
+    CurrentDate,
+    CurrentTime,
+}
+
+impl DefaultKind {
+    pub const fn allowed_in(self, kinds: Option<&[DefaultKind]>) -> bool {
+        let kinds = match kinds {
+            Some(kinds) => kinds,
+            None => return true,
+        };
+        let mut i = 0;
+        while i < kinds.len() {
+            if kinds[i] as u8 == self as u8 {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+}
+
+/// The storage classes of SQLite values. Results whose class is known at compile time, like
+/// `count(...)` or `EXISTS`, are checked against the field's [`DbType::RESULT_KINDS`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResultKind {
+    Integer,
+    Real,
+    Text,
+    Blob,
+}
+
+impl ResultKind {
+    pub const fn readable_as(self, kinds: Option<&[ResultKind]>) -> bool {
+        let kinds = match kinds {
+            Some(kinds) => kinds,
+            None => return true,
+        };
+        let mut i = 0;
+        while i < kinds.len() {
+            if kinds[i] as u8 == self as u8 {
+                return true;
+            }
+            i += 1;
+        }
+        false
+    }
+}
+
+/// Builder state of a required field that has been set.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldSet;
+
+/// Builder state of a required field that hasn't been set yet.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldUnset;
+
+/// Returned (boxed in [`rusqlite::Error::ToSqlConversionFailure`]) when an integer does not fit in
+/// SQLite's signed 64-bit storage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegerOverflow {
+    pub table: &'static str,
+    pub column: &'static str,
+}
+
+impl Display for IntegerOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "value for {}.{} does not fit in a signed 64-bit integer, use `#[store_as(text)]` or `#[store_as(blob)]`",
+            self.table, self.column
+        )
+    }
+}
+
+impl std::error::Error for IntegerOverflow {}
+
+/// A value bound by the generated builders. Checked integer conversions that fail are reported
+/// as [`IntegerOverflow`] naming the column instead of a bare [`TryFromIntError`].
+pub struct CheckedValue<T> {
+    pub table: &'static str,
+    pub column: &'static str,
+    pub value: T,
+}
+
+impl<T: ToSql> ToSql for CheckedValue<T> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        self.value.to_sql().map_err(|err| match err {
+            rusqlite::Error::ToSqlConversionFailure(e) if e.is::<TryFromIntError>() => {
+                rusqlite::Error::ToSqlConversionFailure(Box::new(IntegerOverflow {
+                    table: self.table,
+                    column: self.column,
+                }))
+            }
+            err => err,
+        })
+    }
+}
+
+/// Stores the wrapped value losslessly as its `TEXT` representation. Used by `#[store_as(text)]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsText<T>(pub T);
+
+impl<T: Display> ToSql for AsText<T> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0.to_string()))
+    }
+}
+
+impl<T> FromSql for AsText<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value
+            .as_str()?
+            .parse()
+            .map(AsText)
+            .map_err(FromSqlError::other)
+    }
+}
+
+/// Fixed width big-endian byte encoding, so that blobs compare in the same order as the values.
+pub trait BlobEncode: Sized {
+    fn to_blob(&self) -> Vec<u8>;
+    fn from_blob(blob: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_blob_encode {
+    ($($ty:ty) *) => {
+        $(
+        impl BlobEncode for $ty {
+            fn to_blob(&self) -> Vec<u8> {
+                self.to_be_bytes().to_vec()
+            }
+            fn from_blob(blob: &[u8]) -> Option<Self> {
+                Some(Self::from_be_bytes(blob.try_into().ok()?))
+            }
+        })*
+    };
+}
+
+impl_blob_encode!(u32 u64 u128 usize);
+
+/// Stores the wrapped value losslessly as a `BLOB`. Used by `#[store_as(blob)]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AsBlob<T>(pub T);
+
+impl<T: BlobEncode> ToSql for AsBlob<T> {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.0.to_blob()))
+    }
+}
+
+impl<T: BlobEncode> FromSql for AsBlob<T> {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let blob = value.as_blob()?;
+        T::from_blob(blob)
+            .map(AsBlob)
+            .ok_or(FromSqlError::InvalidBlobSize {
+                expected_size: std::mem::size_of::<T>(),
+                blob_size: blob.len(),
+            })
+    }
 }
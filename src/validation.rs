@@ -0,0 +1,208 @@
+use std::{
+    fmt::{Debug, Display},
+    ops::RangeBounds,
+    sync::OnceLock,
+};
+
+use regex::Regex;
+
+/// A `#[validate(...)]` rule a field broke.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub field: &'static str,
+    /// The rule, one of `length`, `email`, `range`, `regex` or `with`.
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+/// Every `#[validate(...)]` rule the fields of a row broke, returned by the generated
+/// `validate()`, which `build*` and `update` call before touching the database.
+///
+/// ```ignore
+/// #[derive(DbTable)]
+/// struct User {
+///     #[primary_key]
+///     id: i64,
+///     #[validate(length(min = 1, max = 255))]
+///     name: String,
+///     #[validate(email)]
+///     email: String,
+///     #[validate(range(0..=120), check)]
+///     age: u8,
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationErrors {
+    pub table: &'static str,
+    pub errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new(table: &'static str) -> Self {
+        Self {
+            table,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, field: &'static str, rule: &'static str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field,
+            rule,
+            message: message.into(),
+        });
+    }
+
+    /// The errors of one field.
+    pub fn field(&self, field: &str) -> impl Iterator<Item = &FieldError> {
+        self.errors.iter().filter(move |e| e.field == field)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// `Ok` when no rule was broken.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() { Ok(()) } else { Err(self) }
+    }
+
+    /// `#[validate(length(min = .., max = ..))]`
+    pub fn length<T: ValidateLength + ?Sized>(
+        &mut self,
+        field: &'static str,
+        value: &T,
+        min: Option<usize>,
+        max: Option<usize>,
+    ) {
+        let len = value.validate_length();
+        if min.is_some_and(|min| len < min) || max.is_some_and(|max| len > max) {
+            let message = match (min, max) {
+                (Some(min), Some(max)) => format!("length must be between {min} and {max}"),
+                (Some(min), None) => format!("length must be at least {min}"),
+                (None, _) => format!("length must be at most {}", max.unwrap_or_default()),
+            };
+            self.push(field, "length", message);
+        }
+    }
+
+    /// `#[validate(email)]`
+    pub fn email(&mut self, field: &'static str, value: &str) {
+        if !is_email(value) {
+            self.push(field, "email", "must be an email address");
+        }
+    }
+
+    /// `#[validate(range(..))]`
+    pub fn range<T: PartialOrd, R: RangeBounds<T> + Debug>(
+        &mut self,
+        field: &'static str,
+        value: &T,
+        range: R,
+    ) {
+        if !range.contains(value) {
+            self.push(field, "range", format!("must be in {range:?}"));
+        }
+    }
+
+    /// `#[validate(regex = "..")]`
+    pub fn regex(&mut self, field: &'static str, value: &str, regex: &LazyRegex) {
+        if !regex.get().is_match(value) {
+            self.push(
+                field,
+                "regex",
+                format!("must match `{}`", regex.get().as_str()),
+            );
+        }
+    }
+
+    /// `#[validate(with = path)]`, a function returning `Err(message)` for invalid values.
+    pub fn with<E: Display>(&mut self, field: &'static str, result: Result<(), E>) {
+        if let Err(err) = result {
+            self.push(field, "with", err.to_string());
+        }
+    }
+}
+
+impl Display for ValidationErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid {}: ", self.table)?;
+        for (i, err) in self.errors.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{err}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// The length checked by `#[validate(length(..))]`, in characters for strings.
+pub trait ValidateLength {
+    fn validate_length(&self) -> usize;
+}
+
+impl ValidateLength for str {
+    fn validate_length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl ValidateLength for String {
+    fn validate_length(&self) -> usize {
+        self.as_str().validate_length()
+    }
+}
+
+impl<T> ValidateLength for [T] {
+    fn validate_length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> ValidateLength for Vec<T> {
+    fn validate_length(&self) -> usize {
+        self.len()
+    }
+}
+
+/// A `#[validate(regex = "..")]` pattern, compiled on first use. The derive already checked
+/// its syntax.
+pub struct LazyRegex {
+    pattern: &'static str,
+    regex: OnceLock<Regex>,
+}
+
+impl LazyRegex {
+    pub const fn new(pattern: &'static str) -> Self {
+        Self {
+            pattern,
+            regex: OnceLock::new(),
+        }
+    }
+
+    pub fn get(&self) -> &Regex {
+        self.regex
+            .get_or_init(|| Regex::new(self.pattern).expect("checked by the derive"))
+    }
+}
+
+/// A single `@` between a non-empty local part and a dotted domain, without whitespace.
+fn is_email(value: &str) -> bool {
+    let Some((local, domain)) = value.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !domain.contains('@')
+        && !value.chars().any(char::is_whitespace)
+        && domain.split('.').count() > 1
+        && domain.split('.').all(|part| !part.is_empty())
+}
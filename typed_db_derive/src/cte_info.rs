@@ -1,7 +1,12 @@
 use quote::{ToTokens, quote};
-use syn::{Result, spanned::Spanned};
+use syn::{LitStr, Result, spanned::Spanned};
 
-use crate::cte_params::{CteFieldParam, CteTableParams};
+use crate::{
+    cte_params::{
+        CteFieldAggregate, CteFieldExists, CteFieldParam, CteFrom, CteTableParams, RecursiveCte,
+    },
+    sql_validation::{check_expr, check_where, expr_kind},
+};
 
 pub struct CteFieldInfo {
     #[allow(unused)]
@@ -13,65 +18,163 @@ pub struct CteFieldInfo {
 }
 
 impl CteFieldInfo {
-    fn param(&self) -> Result<Option<CteFieldParam>> {
-        let param_attrs = self
+    /// The one `#[param]`, `#[expr]`, `#[aggregate]` or `#[exists]` attribute describing the field.
+    fn source(&self) -> Result<CteFieldSource> {
+        let attrs = self
             .attributes
             .iter()
-            .filter(|attr| attr.path().is_ident("param"))
+            .filter(|attr| {
+                ["param", "expr", "aggregate", "exists"]
+                    .iter()
+                    .any(|name| attr.path().is_ident(name))
+            })
             .collect::<Vec<_>>();
 
-        if param_attrs.len() > 1 {
+        if attrs.len() > 1 {
             return Err(syn::Error::new(
                 self.name.span(),
-                "Only one param attribute is allowed per field",
+                "Only one of the param, expr, aggregate or exists attributes is allowed per field",
             ));
         }
-        let attr = param_attrs.into_iter().next();
 
-        let attr = match attr {
+        let attr = match attrs.into_iter().next() {
             Some(attr) => attr,
-            None => return Ok(None),
+            None => {
+                return Err(syn::Error::new(
+                    self.name.span(),
+                    "`#[param(...)]`, `#[expr(...)]`, `#[aggregate(...)]` or `#[exists(...)]` attribute needed",
+                ));
+            }
         };
 
-        Ok(Some(attr.parse_args()?))
+        let source = if attr.path().is_ident("param") {
+            CteFieldSource::Param(attr.parse_args()?)
+        } else if attr.path().is_ident("expr") {
+            CteFieldSource::Expr(attr.parse_args()?)
+        } else if attr.path().is_ident("aggregate") {
+            CteFieldSource::Aggregate(attr.parse_args()?)
+        } else {
+            CteFieldSource::Exists(attr.parse_args()?)
+        };
+        Ok(source)
     }
-    pub fn select_stmt(&self) -> Result<(proc_macro2::TokenStream, String)> {
+
+    /// Compile time check that a result of the given `ResultKind` can be read into the field.
+    fn kind_check(&self, kind: &str) -> proc_macro2::TokenStream {
+        let kind = syn::Ident::new(kind, self.name.span());
+        let ty = &self.ty;
         let name = &self.name;
-        let cte = match self.param()? {
-            Some(p) => p,
-            None => {
-                return Err(syn::Error::new(
-                    name.span(),
-                    "`#[param(...)] attribute needed",
-                ));
+        quote! {
+            const _: () = assert!(
+                ResultKind::#kind.readable_as(<#ty as DbType>::RESULT_KINDS),
+                concat!("the result of `", stringify!(#name), "` doesn't match its type"),
+            );
+        }
+    }
+
+    /// The field's column of the final `SELECT`. The subqueries only join the `params` table when
+    /// the CTE has parameters.
+    pub fn select_stmt(&self, has_params: bool) -> Result<(proc_macro2::TokenStream, String)> {
+        let name = &self.name;
+        let params = if has_params { ", params" } else { "" };
+        let out = match self.source()? {
+            CteFieldSource::Param(cte) => {
+                let check = cte.validity_check(&self.ty);
+                let CteFieldParam {
+                    table: table_name,
+                    field_name,
+                    val: where_clause,
+                    table_shorthand,
+                } = cte;
+                check_where(&where_clause, &table_shorthand)?;
+                let table_shorthand = table_shorthand.value();
+                let s = format!(
+                    "(SELECT {table_shorthand}.{field_name} FROM {} AS {table_shorthand}{params} WHERE {}) AS {name}",
+                    table_name.into_token_stream(),
+                    where_clause.value()
+                );
+                (check, s)
+            }
+            CteFieldSource::Expr(expr) => {
+                check_expr(&expr)?;
+                // Other expressions are checked when the row is read
+                let check = match expr_kind(&expr) {
+                    Some(kind) => self.kind_check(kind),
+                    None => quote! {},
+                };
+                let s = format!("({}) AS {name}", expr.value());
+                (check, s)
+            }
+            CteFieldSource::Aggregate(CteFieldAggregate { func, param }) => {
+                let check = match func.result_kind() {
+                    Some(kind) => self.kind_check(kind),
+                    None => param.validity_check(&self.ty),
+                };
+                let CteFieldParam {
+                    table: table_name,
+                    field_name,
+                    val: where_clause,
+                    table_shorthand,
+                } = param;
+                check_where(&where_clause, &table_shorthand)?;
+                let table_shorthand = table_shorthand.value();
+                let s = format!(
+                    "(SELECT {}({table_shorthand}.{field_name}) FROM {} AS {table_shorthand}{params} WHERE {}) AS {name}",
+                    func.sql(),
+                    table_name.into_token_stream(),
+                    where_clause.value()
+                );
+                (check, s)
+            }
+            CteFieldSource::Exists(CteFieldExists {
+                table,
+                table_shorthand,
+                val: where_clause,
+            }) => {
+                check_where(&where_clause, &table_shorthand)?;
+                let check = self.kind_check("Integer");
+                let table_shorthand = table_shorthand.value();
+                let s = format!(
+                    "EXISTS (SELECT 1 FROM {} AS {table_shorthand}{params} WHERE {}) AS {name}",
+                    table.into_token_stream(),
+                    where_clause.value()
+                );
+                (check, s)
             }
         };
-        let check = cte.validity_check(&self.ty);
-        let CteFieldParam {
-            table: table_name,
-            field_name,
-            val: where_clause,
-            table_shorthand,
-        } = cte;
-        let table_shorthand = table_shorthand.value();
-        let s = format!(
-            "(SELECT {table_shorthand}.{field_name} FROM {} AS {table_shorthand}, params WHERE {}) AS {name}",
-            table_name.into_token_stream(),
-            where_clause.value()
-        );
 
-        Ok((check, s))
+        Ok(out)
+    }
+}
+
+impl CteFieldSource {
+    /// The table the field's subquery reads from, if any.
+    fn table(&self) -> Option<&syn::Type> {
+        match self {
+            CteFieldSource::Param(param) => Some(&param.table),
+            CteFieldSource::Expr(_) => None,
+            CteFieldSource::Aggregate(aggregate) => Some(&aggregate.param.table),
+            CteFieldSource::Exists(exists) => Some(&exists.table),
+        }
     }
 }
 
+enum CteFieldSource {
+    Param(CteFieldParam),
+    Expr(LitStr),
+    Aggregate(CteFieldAggregate),
+    Exists(CteFieldExists),
+}
+
 pub struct CteInfo {
+    pub visibility: syn::Visibility,
     pub name: syn::Ident,
     pub fields: Vec<CteFieldInfo>,
     pub attributes: Vec<syn::Attribute>,
 }
 
 impl CteInfo {
-    fn cte_str_params(&self) -> Result<String> {
+    fn params(&self) -> Result<Option<CteTableParams>> {
         let cte_attrs = self
             .attributes
             .iter()
@@ -85,20 +188,74 @@ impl CteInfo {
             ));
         }
 
-        let attr = cte_attrs.into_iter().next();
-        let attr = match attr {
-            Some(a) => a,
+        cte_attrs
+            .into_iter()
+            .next()
+            .map(|attr| attr.parse_args())
+            .transpose()
+    }
+
+    fn cte_from(&self) -> Result<Option<CteFrom>> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("cte_from"))
+            .collect::<Vec<_>>();
+
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].path().span(),
+                "Only one cte_from attribute is allowed for the CTE",
+            ));
+        }
+
+        attrs
+            .into_iter()
+            .next()
+            .map(|attr| attr.parse_args())
+            .transpose()
+    }
+
+    pub fn params_name(&self) -> syn::Ident {
+        let name = &self.name;
+        syn::Ident::new(&format!("{name}Params"), name.span())
+    }
+
+    fn recursive_cte(&self) -> Result<Option<RecursiveCte>> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("recursive_cte"))
+            .collect::<Vec<_>>();
+
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].path().span(),
+                "Only one recursive_cte attribute is allowed for the CTE",
+            ));
+        }
+
+        attrs
+            .into_iter()
+            .next()
+            .map(|attr| attr.parse_args())
+            .transpose()
+    }
+
+    /// The `params AS (...)` part of the `WITH` clause.
+    fn cte_str_params(&self) -> Result<String> {
+        let CteTableParams { param_list } = match self.params()? {
+            Some(params) => params,
             None => return Ok(String::new()),
         };
-
-        let CteTableParams { param_list } = attr.parse_args()?;
+        // Named placeholders are still numbered in order, so positional binding keeps working
         let param_list = param_list
-            .into_iter()
-            .map(|p| format!("? AS {}", p.value()))
+            .iter()
+            .map(|p| format!(":{0} AS {0}", p.name()))
             .collect::<Box<[String]>>()
             .join(", ");
         let s = format!(
-            "WITH params AS (
+            "params AS (
 	SELECT {param_list}
 )",
         );
@@ -117,7 +274,7 @@ impl CteInfo {
             .fields
             .iter()
             .map(|f| {
-                let (a, b) = f.select_stmt()?;
+                let (a, b) = f.select_stmt(!param_str.is_empty())?;
                 checks.push(a);
                 Ok(b)
             })
@@ -128,35 +285,141 @@ impl CteInfo {
             Err(err) => return err.to_compile_error(),
         };
 
-        let s = format!("{param_str}SELECT {fields};");
+        let cte_from = match self.cte_from() {
+            Ok(cte_from) => cte_from,
+            Err(err) => return err.to_compile_error(),
+        };
+        let recursive = match self.recursive_cte() {
+            Ok(recursive) => recursive,
+            Err(err) => return err.to_compile_error(),
+        };
+        if cte_from.is_some() && recursive.is_some() {
+            return syn::Error::new(
+                self.name.span(),
+                "`#[cte_from]` and `#[recursive_cte]` can't be combined",
+            )
+            .to_compile_error();
+        }
+
+        let mut ctes = Vec::new();
+        if !param_str.is_empty() {
+            ctes.push(param_str.clone());
+        }
+        let mut order_by = "";
+        if let Some(recursive) = &recursive {
+            if let Err(err) = recursive.check_sql() {
+                return err.to_compile_error();
+            }
+            checks.push(recursive.validity_check());
+            ctes.push(recursive.tree_cte(!param_str.is_empty()));
+            order_by = " ORDER BY tree.depth";
+        }
+        let with = match (ctes.is_empty(), recursive.is_some()) {
+            (true, _) => String::new(),
+            (false, false) => format!("WITH {}", ctes.join(",\n")),
+            (false, true) => format!("WITH RECURSIVE {}", ctes.join(",\n")),
+        };
+        // `#[expr]` fields refer to the params directly, and with `#[cte_from]` the subqueries are
+        // correlated to the base table's rows
+        let mut from_tables = Vec::new();
+        let mut where_clause = String::new();
+        if let Some(CteFrom {
+            table,
+            table_shorthand,
+            where_clause: base_where,
+        }) = cte_from
+        {
+            from_tables.push(format!(
+                "{} AS {}",
+                table.into_token_stream(),
+                table_shorthand.value()
+            ));
+            if let Some(base_where) = base_where {
+                if let Err(err) = check_where(&base_where, &table_shorthand) {
+                    return err.to_compile_error();
+                }
+                where_clause = format!(" WHERE {}", base_where.value());
+            }
+        }
+        if recursive.is_some() {
+            from_tables.push("tree".to_string());
+        }
+        if !param_str.is_empty() {
+            from_tables.push("params".to_string());
+        }
+        let from = if from_tables.is_empty() {
+            String::new()
+        } else {
+            format!(" FROM {}{where_clause}", from_tables.join(", "))
+        };
+        let s = format!("{with}SELECT {fields}{from}{order_by};");
 
         quote! { #(#checks)* #s }
     }
+    /// The `<Name>Params` struct for `#[cte_params(...)]`.
+    pub fn impl_params_struct(&self) -> proc_macro2::TokenStream {
+        let params = match self.params() {
+            Ok(Some(params)) => params,
+            Ok(None) => return quote! {},
+            Err(err) => return err.to_compile_error(),
+        };
+        let fields = match params.fields() {
+            Ok(fields) => fields,
+            Err(err) => return err.to_compile_error(),
+        };
+        let vis = &self.visibility;
+        let params_name = self.params_name();
+        let doc = format!(
+            "Parameters of [`{}`], bound by name into its `params` table.",
+            self.name
+        );
+        let named = fields.iter().map(|(name, _)| {
+            let placeholder = format!(":{name}");
+            quote! { (#placeholder, &self.#name as &dyn ::rusqlite::ToSql), }
+        });
+        let fields = fields.iter().map(|(name, ty)| quote! { pub #name: #ty, });
+        quote! {
+            #[automatically_derived]
+            #[derive(Debug, Clone)]
+            #[doc = #doc]
+            #vis struct #params_name {
+                #(#fields)*
+            }
+
+            #[automatically_derived]
+            impl CteParams for #params_name {
+                fn named_params(&self) -> Vec<(&'static str, &dyn ::rusqlite::ToSql)> {
+                    vec![#(#named)*]
+                }
+            }
+        }
+    }
+
     pub fn impl_cte(&self) -> proc_macro2::TokenStream {
         let name = &self.name;
         let cte_str_fn = self.impl_cte_str_fn();
+        let params_ty = match self.params() {
+            Ok(Some(_)) => {
+                let params_name = self.params_name();
+                quote! { #params_name }
+            }
+            Ok(None) => quote! { () },
+            Err(err) => return err.to_compile_error(),
+        };
         let field_getters = self.fields.iter().map(|f| {
             let f_name = &f.name;
             quote! { #f_name: row.get(stringify!(#f_name))?,}
         });
         quote! {
             impl CommonTableExpression for #name {
+                type Params = #params_ty;
                 fn cte_str() -> &'static str {
                     #cte_str_fn
                 }
-                fn select(
-                    conn: &rusqlite::Connection,
-                    params: impl rusqlite::Params,
-                ) -> rusqlite::Result<Box<[Self]>> {
-                    let mut stmt = conn.prepare(&Self::cte_str())?;
-                    let rows = stmt
-                        .query_map(params, |row| {
-                            Ok(Self {
-                                #(#field_getters)*
-                            })
-                        })?
-                        .collect::<rusqlite::Result<_>>()?;
-                    Ok(rows)
+                fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+                    Ok(Self {
+                        #(#field_getters)*
+                    })
                 }
             }
         }
@@ -167,26 +430,84 @@ impl CteInfo {
         quote! {
             impl #name {
                 #[automatically_derived]
-                pub fn print_query_plan(conn: &rusqlite::Connection, params: impl rusqlite::Params) -> Result<(), rusqlite::Error> {
-                    let query_plan_str = format!("EXPLAIN QUERY PLAN {}", Self::cte_str());
-                    let mut stmt = conn.prepare(&query_plan_str)?;
-                    let rows = stmt.query_map(params, |row| {
-                        Ok((
-                            row.get::<_, i32>(0)?,
-                            row.get::<_, i32>(1)?,
-                            row.get::<_, i32>(2)?,
-                            row.get::<_, String>(3)?,
-                        ))
-                    })?;
-                    println!("Query Plan:");
-                    for row in rows {
-                        let (selectid, order, from, detail) = row?;
-                        println!(
-                            "selectid: {}, order: {}, from: {}, detail: {}",
-                            selectid, order, from, detail
-                        );
-                    }
+                pub fn print_query_plan(conn: &rusqlite::Connection, params: impl rusqlite::Params) -> ::core::result::Result<(), ::typed_db::Error> {
+                    println!("{}", Self::explain_raw(conn, params)?);
+                    Ok(())
+                }
+            }
+        }
+    }
+
+    /// `DbView` for CTEs without parameters, as views can't take any.
+    pub fn impl_view(&self) -> proc_macro2::TokenStream {
+        let name = &self.name;
+        match self.params() {
+            Ok(None) => {}
+            Ok(Some(_)) => {
+                return syn::Error::new(
+                    name.span(),
+                    "Views can't have parameters, remove `#[cte_params(...)]`",
+                )
+                .to_compile_error();
+            }
+            Err(err) => return err.to_compile_error(),
+        }
+        quote! {
+            #[automatically_derived]
+            impl DbView for #name {
+                const VIEW_NAME: &'static str = stringify!(#name);
+            }
+        }
+    }
+
+    /// Every table the CTE reads from, in order of first use.
+    fn referenced_tables(&self) -> Result<Vec<syn::Type>> {
+        let mut tables = Vec::new();
+        if let Some(cte_from) = self.cte_from()? {
+            tables.push(cte_from.table);
+        }
+        if let Some(recursive) = self.recursive_cte()? {
+            tables.push(recursive.table);
+        }
+        for field in &self.fields {
+            if let Some(table) = field.source()?.table() {
+                tables.push(table.clone());
+            }
+        }
+        let mut seen = std::collections::HashSet::new();
+        tables.retain(|table| seen.insert(table.to_token_stream().to_string()));
+        Ok(tables)
+    }
 
+    /// With the `sql-tests` feature, a test preparing the full `cte_str` against the referenced
+    /// tables, catching unknown columns and functions the fragment parser can't see. The columns
+    /// of other structs aren't visible during expansion, so this can only run under `cargo test`.
+    fn impl_cte_tests(&self) -> proc_macro2::TokenStream {
+        if !cfg!(feature = "sql-tests") {
+            return quote! {};
+        }
+        let name = &self.name;
+        let test_name = syn::Ident::new(&(name.to_string() + "_gen_tests"), name.span());
+        let tables = match self.referenced_tables() {
+            Ok(tables) => tables,
+            Err(err) => return err.to_compile_error(),
+        };
+
+        quote! {
+            #[cfg(test)]
+            #[automatically_derived]
+            #[allow(non_snake_case)]
+            mod #test_name {
+                use super::*;
+
+                #[test]
+                fn prepare() -> ::core::result::Result<(), Box<dyn std::error::Error>> {
+                    let conn = ::rusqlite::Connection::open(":memory:")?;
+                    #(<#tables as DbTable>::create_table(&conn)?;)*
+                    let sql = <#name as CommonTableExpression>::cte_str();
+                    if let Err(err) = conn.prepare(sql) {
+                        panic!(concat!("invalid SQL in `", stringify!(#name), "`: {}\n{}"), err, sql);
+                    }
                     Ok(())
                 }
             }
@@ -195,11 +516,15 @@ impl CteInfo {
 
     pub fn impls(&self) -> proc_macro2::TokenStream {
         let cte_impl = self.impl_cte();
+        let params_impl = self.impl_params_struct();
         let self_impl = self.impl_self();
+        let tests_impl = self.impl_cte_tests();
 
         quote! {
             #cte_impl
+            #params_impl
             #self_impl
+            #tests_impl
         }
     }
 }
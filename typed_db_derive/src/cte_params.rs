@@ -1,10 +1,11 @@
+use quote::quote;
 use syn::{
-    Ident, LitStr, Result, Token, Type,
+    Ident, LitInt, LitStr, Result, Token, Type,
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
 };
 
-use crate::structs::TableColonField;
+use crate::{sql_validation::check_expr, structs::TableColonField};
 
 #[derive(Debug, Clone)]
 pub struct CteFieldParam {
@@ -46,16 +47,377 @@ impl Parse for CteFieldParam {
     }
 }
 
+mod kw {
+    syn::custom_keyword!(count);
+    syn::custom_keyword!(sum);
+    syn::custom_keyword!(min);
+    syn::custom_keyword!(max);
+    syn::custom_keyword!(avg);
+    syn::custom_keyword!(group_concat);
+    syn::custom_keyword!(parent);
+    syn::custom_keyword!(child);
+    syn::custom_keyword!(start);
+    syn::custom_keyword!(direction);
+    syn::custom_keyword!(max_depth);
+    syn::custom_keyword!(descendants);
+    syn::custom_keyword!(ancestors);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum AggregateFn {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    GroupConcat,
+}
+
+impl AggregateFn {
+    pub fn sql(&self) -> &'static str {
+        match self {
+            AggregateFn::Count => "count",
+            AggregateFn::Sum => "sum",
+            AggregateFn::Min => "min",
+            AggregateFn::Max => "max",
+            AggregateFn::Avg => "avg",
+            AggregateFn::GroupConcat => "group_concat",
+        }
+    }
+
+    /// The `ResultKind` the result has regardless of the column, if any.
+    pub fn result_kind(&self) -> Option<&'static str> {
+        match self {
+            AggregateFn::Count => Some("Integer"),
+            AggregateFn::Avg => Some("Real"),
+            AggregateFn::GroupConcat => Some("Text"),
+            AggregateFn::Sum | AggregateFn::Min | AggregateFn::Max => None,
+        }
+    }
+}
+
+impl Parse for AggregateFn {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::count) {
+            input.parse::<kw::count>()?;
+            Ok(AggregateFn::Count)
+        } else if lookahead.peek(kw::sum) {
+            input.parse::<kw::sum>()?;
+            Ok(AggregateFn::Sum)
+        } else if lookahead.peek(kw::min) {
+            input.parse::<kw::min>()?;
+            Ok(AggregateFn::Min)
+        } else if lookahead.peek(kw::max) {
+            input.parse::<kw::max>()?;
+            Ok(AggregateFn::Max)
+        } else if lookahead.peek(kw::avg) {
+            input.parse::<kw::avg>()?;
+            Ok(AggregateFn::Avg)
+        } else if lookahead.peek(kw::group_concat) {
+            input.parse::<kw::group_concat>()?;
+            Ok(AggregateFn::GroupConcat)
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
+/// `#[aggregate(count, Table::field as "t", "where ...")]`
+#[derive(Debug, Clone)]
+pub struct CteFieldAggregate {
+    pub func: AggregateFn,
+    pub param: CteFieldParam,
+}
+
+impl Parse for CteFieldAggregate {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let func = input.parse()?;
+        let _: Token![,] = input.parse()?;
+        let param = input.parse()?;
+        Ok(Self { func, param })
+    }
+}
+
+/// `#[exists(Table as "t", "where ...")]`
+#[derive(Debug, Clone)]
+pub struct CteFieldExists {
+    pub table: Type,
+    pub table_shorthand: LitStr,
+    pub val: LitStr,
+}
+
+impl Parse for CteFieldExists {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let table = input.parse()?;
+        let _: Token![as] = input.parse()?;
+        let table_shorthand = input.parse()?;
+        let _: Token![,] = input.parse()?;
+        let val = input.parse()?;
+        Ok(Self {
+            table,
+            table_shorthand,
+            val,
+        })
+    }
+}
+
+/// `#[cte_from(Table as "t", where = "...")]`, the base table the CTE returns one row for each row of.
+#[derive(Debug, Clone)]
+pub struct CteFrom {
+    pub table: Type,
+    pub table_shorthand: LitStr,
+    pub where_clause: Option<LitStr>,
+}
+
+impl Parse for CteFrom {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let table = input.parse()?;
+        let _: Token![as] = input.parse()?;
+        let table_shorthand = input.parse()?;
+        let mut where_clause = None;
+        if !input.is_empty() {
+            let _: Token![,] = input.parse()?;
+            let _: Token![where] = input.parse()?;
+            let _: Token![=] = input.parse()?;
+            where_clause = Some(input.parse()?);
+        }
+        Ok(Self {
+            table,
+            table_shorthand,
+            where_clause,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum RecursiveDirection {
+    Descendants,
+    Ancestors,
+}
+
+impl Parse for RecursiveDirection {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::descendants) {
+            input.parse::<kw::descendants>()?;
+            Ok(RecursiveDirection::Descendants)
+        } else if lookahead.peek(kw::ancestors) {
+            input.parse::<kw::ancestors>()?;
+            Ok(RecursiveDirection::Ancestors)
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
+/// `#[recursive_cte(Table as "t", parent = field, child = field, start = "...", ...)]`
+///
+/// Walks the `parent -> child` edges of `Table` from the `start` node, exposing the visited rows as
+/// `tree(node, parent, depth, path)` to the fields. `direction = ancestors` walks the edges in
+/// reverse, `max_depth = n` stops after `n` edges and `where = "..."` filters the edges. Nodes
+/// already on the path are never revisited, so cycles terminate.
+#[derive(Debug, Clone)]
+pub struct RecursiveCte {
+    pub table: Type,
+    pub table_shorthand: LitStr,
+    pub parent: Ident,
+    pub child: Ident,
+    pub start: LitStr,
+    pub direction: RecursiveDirection,
+    pub max_depth: Option<LitInt>,
+    pub where_clause: Option<LitStr>,
+}
+
+impl Parse for RecursiveCte {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let table = input.parse()?;
+        let _: Token![as] = input.parse()?;
+        let table_shorthand = input.parse()?;
+
+        let mut parent = None;
+        let mut child = None;
+        let mut start = None;
+        let mut direction = RecursiveDirection::Descendants;
+        let mut max_depth = None;
+        let mut where_clause = None;
+        while !input.is_empty() {
+            let _: Token![,] = input.parse()?;
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::parent) {
+                input.parse::<kw::parent>()?;
+                input.parse::<Token![=]>()?;
+                parent = Some(input.parse()?);
+            } else if lookahead.peek(kw::child) {
+                input.parse::<kw::child>()?;
+                input.parse::<Token![=]>()?;
+                child = Some(input.parse()?);
+            } else if lookahead.peek(kw::start) {
+                input.parse::<kw::start>()?;
+                input.parse::<Token![=]>()?;
+                start = Some(input.parse()?);
+            } else if lookahead.peek(kw::direction) {
+                input.parse::<kw::direction>()?;
+                input.parse::<Token![=]>()?;
+                direction = input.parse()?;
+            } else if lookahead.peek(kw::max_depth) {
+                input.parse::<kw::max_depth>()?;
+                input.parse::<Token![=]>()?;
+                max_depth = Some(input.parse()?);
+            } else if lookahead.peek(Token![where]) {
+                input.parse::<Token![where]>()?;
+                input.parse::<Token![=]>()?;
+                where_clause = Some(input.parse()?);
+            } else {
+                return Err(lookahead.error());
+            }
+        }
+
+        let missing = |key| syn::Error::new(input.span(), format!("`{key} = ...` is required"));
+        Ok(Self {
+            table,
+            table_shorthand,
+            parent: parent.ok_or_else(|| missing("parent"))?,
+            child: child.ok_or_else(|| missing("child"))?,
+            start: start.ok_or_else(|| missing("start"))?,
+            direction,
+            max_depth,
+            where_clause,
+        })
+    }
+}
+
+impl RecursiveCte {
+    /// Both ends of the edge must be the same type, i.e. the relation refers back to one table.
+    pub fn validity_check(&self) -> proc_macro2::TokenStream {
+        let table = &self.table;
+        let parent = &self.parent;
+        let child = &self.child;
+        quote::quote! {
+            {
+                let x: Option<#table> = None;
+                x.map(|it| [it.#parent, it.#child])
+            };
+        }
+    }
+
+    /// Parses the `start` expression and the `where` edge filter.
+    pub fn check_sql(&self) -> Result<()> {
+        check_expr(&self.start)?;
+        if let Some(where_clause) = &self.where_clause {
+            check_expr(where_clause)?;
+        }
+        Ok(())
+    }
+
+    /// The `tree AS (...)` part of the `WITH RECURSIVE` clause.
+    pub fn tree_cte(&self, has_params: bool) -> String {
+        let table = quote::ToTokens::into_token_stream(&self.table);
+        let t = self.table_shorthand.value();
+        let (node, link) = match self.direction {
+            RecursiveDirection::Descendants => (&self.child, &self.parent),
+            RecursiveDirection::Ancestors => (&self.parent, &self.child),
+        };
+        let params = if has_params { ", params" } else { "" };
+        let edge_filter = self
+            .where_clause
+            .as_ref()
+            .map(|w| format!(" AND ({})", w.value()))
+            .unwrap_or_default();
+        let depth_limit = self
+            .max_depth
+            .as_ref()
+            .map(|d| format!(" AND tree.depth < {}", d.base10_digits()))
+            .unwrap_or_default();
+        format!(
+            "tree(node, parent, depth, path) AS (
+	SELECT {t}.{node}, {t}.{link}, 1, ',' || {t}.{link} || ',' || {t}.{node} || ','
+	FROM {table} AS {t}{params}
+	WHERE {t}.{link} = ({start}){edge_filter}
+	UNION ALL
+	SELECT {t}.{node}, {t}.{link}, tree.depth + 1, tree.path || {t}.{node} || ','
+	FROM {table} AS {t}, tree{params}
+	WHERE {t}.{link} = tree.node
+	AND instr(tree.path, ',' || {t}.{node} || ',') = 0{depth_limit}{edge_filter}
+)",
+            start = self.start.value(),
+        )
+    }
+}
+
+/// A single entry of `#[cte_params(...)]`, either `"name"` or `name: Type`.
+#[derive(Debug, Clone)]
+pub enum CteParam {
+    Untyped(LitStr),
+    Typed(Ident, Box<Type>),
+}
+
+impl CteParam {
+    pub fn name(&self) -> String {
+        match self {
+            CteParam::Untyped(name) => name.value(),
+            CteParam::Typed(name, _) => name.to_string(),
+        }
+    }
+}
+
+impl Parse for CteParam {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(LitStr) {
+            Ok(CteParam::Untyped(input.parse()?))
+        } else if lookahead.peek(Ident) {
+            let name = input.parse()?;
+            let _: Token![:] = input.parse()?;
+            Ok(CteParam::Typed(name, Box::new(input.parse()?)))
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CteTableParams {
-    pub param_list: Box<[LitStr]>,
+    pub param_list: Box<[CteParam]>,
+}
+
+impl CteTableParams {
+    /// The fields of the `<Name>Params` struct: the declared types of `name: Type` params, and
+    /// `rusqlite::types::Value` for the untyped `"name"` form.
+    pub fn fields(&self) -> Result<Vec<(Ident, proc_macro2::TokenStream)>> {
+        self.param_list
+            .iter()
+            .map(|p| match p {
+                CteParam::Typed(name, ty) => Ok((name.clone(), quote! { #ty })),
+                CteParam::Untyped(name) => {
+                    let ident = syn::parse_str::<Ident>(&name.value()).map_err(|_| {
+                        syn::Error::new(name.span(), "Parameter names must be identifiers")
+                    })?;
+                    let ident = Ident::new(&ident.to_string(), name.span());
+                    Ok((ident, quote! { ::rusqlite::types::Value }))
+                }
+            })
+            .collect()
+    }
 }
 
 impl Parse for CteTableParams {
     fn parse(input: ParseStream) -> Result<Self> {
-        let param_list = Punctuated::<LitStr, Token![,]>::parse_terminated(input)?
-            .into_iter()
-            .collect();
+        let param_list: Box<[CteParam]> =
+            Punctuated::<CteParam, Token![,]>::parse_terminated(input)?
+                .into_iter()
+                .collect();
+
+        let typed = param_list
+            .iter()
+            .filter(|p| matches!(p, CteParam::Typed(..)))
+            .count();
+        if typed != 0 && typed != param_list.len() {
+            return Err(syn::Error::new(
+                input.span(),
+                "Either all or none of the cte_params must have types",
+            ));
+        }
 
         Ok(Self { param_list })
     }
@@ -1,7 +1,7 @@
 use std::fmt::Display;
 
 use syn::{
-    LitInt, LitStr, Result,
+    LitByteStr, LitFloat, LitInt, LitStr, Result, Token, parenthesized,
     parse::{Parse, ParseStream},
 };
 
@@ -11,6 +11,7 @@ mod kw {
     syn::custom_keyword!(CURRENT_TIME);
     syn::custom_keyword!(TRUE);
     syn::custom_keyword!(FALSE);
+    syn::custom_keyword!(NULL);
 }
 
 pub enum DefaultValues {
@@ -19,8 +20,52 @@ pub enum DefaultValues {
     CurrentTime,
     True,
     False,
-    IntLiteral(LitInt),
+    Null,
+    IntLiteral {
+        negative: bool,
+        lit: LitInt,
+    },
+    FloatLiteral {
+        negative: bool,
+        lit: LitFloat,
+    },
+    /// Written as a quoted SQL string: `#[default("abc")]` becomes `DEFAULT 'abc'`
     StrLiteral(LitStr),
+    /// Written as an SQL blob: `#[default(b"\x01\x02")]` becomes `DEFAULT X'0102'`
+    BlobLiteral(LitByteStr),
+    /// An arbitrary expression, written verbatim inside parentheses:
+    /// `#[default(("strftime('%s','now')"))]` becomes `DEFAULT (strftime('%s','now'))`
+    Expression(LitStr),
+}
+
+impl DefaultValues {
+    /// The name of the matching `DefaultKind` variant, if the default can be type checked.
+    pub fn kind(&self) -> Option<&'static str> {
+        let kind = match self {
+            DefaultValues::CurrentTimestamp => "CurrentTimestamp",
+            DefaultValues::CurrentDate => "CurrentDate",
+            DefaultValues::CurrentTime => "CurrentTime",
+            DefaultValues::True | DefaultValues::False => "Boolean",
+            DefaultValues::IntLiteral { .. } => "Integer",
+            DefaultValues::FloatLiteral { .. } => "Real",
+            DefaultValues::StrLiteral(_) => "Text",
+            DefaultValues::BlobLiteral(_) => "Blob",
+            DefaultValues::Null | DefaultValues::Expression(_) => return None,
+        };
+        Some(kind)
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            DefaultValues::IntLiteral { negative, .. }
+            | DefaultValues::FloatLiteral { negative, .. } => *negative,
+            _ => false,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, DefaultValues::Null)
+    }
 }
 
 impl Parse for DefaultValues {
@@ -42,10 +87,29 @@ impl Parse for DefaultValues {
         } else if lookahead.peek(kw::FALSE) {
             input.parse::<kw::FALSE>()?;
             Ok(DefaultValues::False)
-        } else if lookahead.peek(LitInt) {
-            Ok(DefaultValues::IntLiteral(input.parse()?))
+        } else if lookahead.peek(kw::NULL) {
+            input.parse::<kw::NULL>()?;
+            Ok(DefaultValues::Null)
+        } else if lookahead.peek(Token![-]) || lookahead.peek(LitInt) || lookahead.peek(LitFloat) {
+            let negative = input.parse::<Option<Token![-]>>()?.is_some();
+            let lookahead = input.lookahead1();
+            if lookahead.peek(LitInt) {
+                let lit = input.parse()?;
+                Ok(DefaultValues::IntLiteral { negative, lit })
+            } else if lookahead.peek(LitFloat) {
+                let lit = input.parse()?;
+                Ok(DefaultValues::FloatLiteral { negative, lit })
+            } else {
+                Err(lookahead.error())
+            }
         } else if lookahead.peek(LitStr) {
             Ok(DefaultValues::StrLiteral(input.parse()?))
+        } else if lookahead.peek(LitByteStr) {
+            Ok(DefaultValues::BlobLiteral(input.parse()?))
+        } else if lookahead.peek(syn::token::Paren) {
+            let content;
+            parenthesized!(content in input);
+            Ok(DefaultValues::Expression(content.parse()?))
         } else {
             Err(lookahead.error())
         }
@@ -60,8 +124,27 @@ impl Display for DefaultValues {
             DefaultValues::CurrentTime => write!(f, "CURRENT_TIME"),
             DefaultValues::True => write!(f, "TRUE"),
             DefaultValues::False => write!(f, "FALSE"),
-            DefaultValues::IntLiteral(lit_int) => write!(f, "{}", lit_int),
-            DefaultValues::StrLiteral(lit_str) => write!(f, "{}", lit_str.value()),
+            DefaultValues::Null => write!(f, "NULL"),
+            DefaultValues::IntLiteral { negative, lit } => {
+                let sign = if *negative { "-" } else { "" };
+                write!(f, "{sign}{}", lit.base10_digits())
+            }
+            DefaultValues::FloatLiteral { negative, lit } => {
+                let sign = if *negative { "-" } else { "" };
+                write!(f, "{sign}{}", lit.base10_digits())
+            }
+            DefaultValues::StrLiteral(lit_str) => {
+                write!(f, "'{}'", lit_str.value().replace('\'', "''"))
+            }
+            DefaultValues::BlobLiteral(lit_bytes) => {
+                let hex = lit_bytes
+                    .value()
+                    .iter()
+                    .map(|b| format!("{b:02X}"))
+                    .collect::<String>();
+                write!(f, "X'{hex}'")
+            }
+            DefaultValues::Expression(expr) => write!(f, "({})", expr.value()),
         }
     }
 }
@@ -0,0 +1,67 @@
+use syn::{
+    LitStr, Result, Token, parenthesized,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+};
+
+mod kw {
+    syn::custom_keyword!(content);
+    syn::custom_keyword!(columns);
+    syn::custom_keyword!(tokenize);
+}
+
+/// `#[fts5(content = User, columns(name, email), tokenize = "porter unicode61")]`, where
+/// `tokenize` is optional.
+#[derive(Debug, Clone)]
+pub struct Fts5Attr {
+    pub content: syn::Type,
+    pub columns: Vec<syn::Ident>,
+    pub tokenize: Option<LitStr>,
+}
+
+impl Parse for Fts5Attr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut content = None;
+        let mut columns = None;
+        let mut tokenize = None;
+        while !input.is_empty() {
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::content) {
+                let kw = input.parse::<kw::content>()?;
+                input.parse::<Token![=]>()?;
+                if content.replace(input.parse()?).is_some() {
+                    return Err(syn::Error::new(kw.span, "Duplicate `content`"));
+                }
+            } else if lookahead.peek(kw::columns) {
+                let kw = input.parse::<kw::columns>()?;
+                let list;
+                parenthesized!(list in input);
+                let parsed = Punctuated::<syn::Ident, Token![,]>::parse_terminated(&list)?;
+                if parsed.is_empty() {
+                    return Err(syn::Error::new(kw.span, "Expected at least one column"));
+                }
+                if columns.replace(parsed.into_iter().collect()).is_some() {
+                    return Err(syn::Error::new(kw.span, "Duplicate `columns`"));
+                }
+            } else if lookahead.peek(kw::tokenize) {
+                let kw = input.parse::<kw::tokenize>()?;
+                input.parse::<Token![=]>()?;
+                if tokenize.replace(input.parse()?).is_some() {
+                    return Err(syn::Error::new(kw.span, "Duplicate `tokenize`"));
+                }
+            } else {
+                return Err(lookahead.error());
+            }
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+        let content = content.ok_or_else(|| input.error("Expected `content = Table`"))?;
+        let columns = columns.ok_or_else(|| input.error("Expected `columns(...)`"))?;
+        Ok(Self {
+            content,
+            columns,
+            tokenize,
+        })
+    }
+}
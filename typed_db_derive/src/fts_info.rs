@@ -0,0 +1,69 @@
+use quote::quote;
+use syn::Result;
+
+use crate::fts5_parser::Fts5Attr;
+
+pub struct FtsInfo {
+    pub name: proc_macro2::Ident,
+    pub attributes: Vec<syn::Attribute>,
+}
+
+impl FtsInfo {
+    /// The one `#[fts5(...)]` attribute.
+    fn fts5(&self) -> Result<Fts5Attr> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("fts5"))
+            .collect::<Vec<_>>();
+        match attrs.as_slice() {
+            [attr] => attr.parse_args(),
+            [] => Err(syn::Error::new(
+                self.name.span(),
+                "`#[fts5(content = Table, columns(...))]` attribute needed",
+            )),
+            [_, attr, ..] => Err(syn::Error::new_spanned(
+                attr,
+                "Only one fts5 attribute allowed per table",
+            )),
+        }
+    }
+
+    pub fn impls(&self) -> proc_macro2::TokenStream {
+        let fts5 = match self.fts5() {
+            Ok(fts5) => fts5,
+            Err(err) => return err.to_compile_error(),
+        };
+        let name = &self.name;
+        let content = &fts5.content;
+        let columns = &fts5.columns;
+        let column_names = columns.iter().map(|c| c.to_string());
+        let options = match &fts5.tokenize {
+            Some(tokenize) => format!(", tokenize = '{}'", tokenize.value().replace('\'', "''")),
+            None => String::new(),
+        };
+        quote! {
+            #[automatically_derived]
+            impl DbFtsTable for #name {
+                type Content = #content;
+                const TABLE_NAME: &'static str = stringify!(#name);
+                const COLUMNS: &'static [&'static str] = &[#(#column_names),*];
+                const OPTIONS: &'static str = #options;
+            }
+
+            // The index refers to the rows of the content table by rowid
+            const _: () = assert!(
+                !<#content as DbTable>::WITHOUT_ROWID,
+                "the content table of a `DbFtsTable` can't be `WITHOUT ROWID`"
+            );
+
+            // The indexed columns must be fields of the content table
+            const _: () = {
+                #[allow(dead_code)]
+                fn check(row: &#content) {
+                    let _ = (#(&row.#columns),*);
+                }
+            };
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::fmt::Display;
+
+use syn::{
+    LitStr, Result, Token,
+    parse::{Parse, ParseStream},
+};
+
+mod kw {
+    syn::custom_keyword!(stored);
+}
+
+/// `#[generated("expr")]` or `#[generated("expr", stored)]`
+#[derive(Debug, Clone)]
+pub struct GeneratedColumn {
+    pub expr: LitStr,
+    pub stored: bool,
+}
+
+impl Parse for GeneratedColumn {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let expr: LitStr = input.parse()?;
+        let mut stored = false;
+        if !input.is_empty() {
+            let _: Token![,] = input.parse()?;
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::stored) {
+                input.parse::<kw::stored>()?;
+                stored = true;
+            } else if lookahead.peek(Token![virtual]) {
+                input.parse::<Token![virtual]>()?;
+            } else {
+                return Err(lookahead.error());
+            }
+        }
+        Ok(Self { expr, stored })
+    }
+}
+
+impl Display for GeneratedColumn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let storage = if self.stored { "STORED" } else { "VIRTUAL" };
+        write!(f, "GENERATED ALWAYS AS ({}) {storage}", self.expr.value())
+    }
+}
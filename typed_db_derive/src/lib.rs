@@ -2,17 +2,43 @@ mod cte_info;
 mod cte_params;
 mod default_value_parser;
 mod foreign_key_parser;
+mod fts5_parser;
+mod fts_info;
+mod generated_column_parser;
+mod sql_validation;
+mod store_as_parser;
 mod structs;
+mod table_options_parser;
+mod validate_parser;
 
 use cte_info::{CteFieldInfo, CteInfo};
+use fts_info::FtsInfo;
 use proc_macro::TokenStream;
-use syn::{Attribute, DataStruct, Ident, spanned::Spanned};
+use syn::{Attribute, DataStruct, Ident, Visibility, spanned::Spanned};
 
 use structs::*;
 
 #[proc_macro_derive(
     DbTable,
-    attributes(default, primary_key, unique, composite_key, foreign_key)
+    attributes(
+        default,
+        primary_key,
+        unique,
+        composite_key,
+        foreign_key,
+        store_as,
+        table,
+        generated,
+        collate,
+        default_fn,
+        validate,
+        hooks,
+        created_at,
+        updated_at,
+        soft_delete,
+        version,
+        history
+    )
 )]
 pub fn dbtable_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
@@ -66,7 +92,10 @@ fn dbtable_struct(data_struct: &DataStruct, name: &Ident, attrs: &[Attribute]) -
     }
 }
 
-#[proc_macro_derive(CommonTableExpression, attributes(param, cte_params))]
+#[proc_macro_derive(
+    CommonTableExpression,
+    attributes(param, expr, aggregate, exists, cte_params, cte_from, recursive_cte)
+)]
 pub fn dbview_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse(input).unwrap();
 
@@ -78,7 +107,7 @@ fn impl_dbview_macro(ast: &syn::DeriveInput) -> TokenStream {
     let attrs = &ast.attrs;
 
     let data = match &ast.data {
-        syn::Data::Struct(data_struct) => cte_struct(data_struct, name, attrs).impls(),
+        syn::Data::Struct(data_struct) => cte_struct(data_struct, &ast.vis, name, attrs).impls(),
         syn::Data::Enum(data_enum) => {
             syn::Error::new(data_enum.enum_token.span(), "Enums are not valid DB Views")
                 .into_compile_error()
@@ -95,7 +124,64 @@ fn impl_dbview_macro(ast: &syn::DeriveInput) -> TokenStream {
     data.into()
 }
 
-fn cte_struct(data_struct: &DataStruct, name: &Ident, attrs: &[Attribute]) -> CteInfo {
+#[proc_macro_derive(DbView)]
+pub fn db_view_derive(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+
+    let data = match &ast.data {
+        syn::Data::Struct(data_struct) => {
+            cte_struct(data_struct, &ast.vis, &ast.ident, &ast.attrs).impl_view()
+        }
+        syn::Data::Enum(data_enum) => {
+            syn::Error::new(data_enum.enum_token.span(), "Enums are not valid DB Views")
+                .into_compile_error()
+        }
+        syn::Data::Union(data_union) => syn::Error::new(
+            data_union.union_token.span(),
+            "Unions are not valid DB Views",
+        )
+        .into_compile_error(),
+    };
+
+    data.into()
+}
+
+#[proc_macro_derive(DbFtsTable, attributes(fts5))]
+pub fn db_fts_table_derive(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+
+    let data = match &ast.data {
+        syn::Data::Struct(data_struct) if data_struct.fields.is_empty() => FtsInfo {
+            name: ast.ident.clone(),
+            attributes: ast.attrs.clone(),
+        }
+        .impls(),
+        syn::Data::Struct(data_struct) => syn::Error::new(
+            data_struct.fields.span(),
+            "FTS tables are unit structs, their rows are the content table's",
+        )
+        .into_compile_error(),
+        syn::Data::Enum(data_enum) => syn::Error::new(
+            data_enum.enum_token.span(),
+            "Enums are not valid FTS tables",
+        )
+        .into_compile_error(),
+        syn::Data::Union(data_union) => syn::Error::new(
+            data_union.union_token.span(),
+            "Unions are not valid FTS tables",
+        )
+        .into_compile_error(),
+    };
+
+    data.into()
+}
+
+fn cte_struct(
+    data_struct: &DataStruct,
+    vis: &Visibility,
+    name: &Ident,
+    attrs: &[Attribute],
+) -> CteInfo {
     let fields = data_struct
         .fields
         .iter()
@@ -113,6 +199,7 @@ fn cte_struct(data_struct: &DataStruct, name: &Ident, attrs: &[Attribute]) -> Ct
         })
         .collect();
     CteInfo {
+        visibility: vis.clone(),
         name: name.clone(),
         fields,
         attributes: attrs.into(),
@@ -0,0 +1,122 @@
+use sqlparser::{
+    ast::{BinaryOperator, Expr, UnaryOperator, Value},
+    dialect::SQLiteDialect,
+    parser::Parser,
+};
+use syn::{LitStr, Result};
+
+/// Parses `sql`, the statement `fragment` is spliced into, reporting any syntax error at the
+/// fragment's literal.
+fn check(fragment: &LitStr, sql: &str) -> Result<()> {
+    let statements = Parser::parse_sql(&SQLiteDialect {}, sql).map_err(|err| {
+        // Positions refer to the surrounding statement rather than the fragment, so leave them out
+        let err = err.to_string();
+        let err = err.split(" at Line: ").next().unwrap_or(&err);
+        syn::Error::new(
+            fragment.span(),
+            format!("invalid SQL `{}`: {err}", fragment.value()),
+        )
+    })?;
+    if statements.len() != 1 {
+        return Err(syn::Error::new(
+            fragment.span(),
+            "SQL fragments can't contain more than one statement",
+        ));
+    }
+    Ok(())
+}
+
+/// A `WHERE` clause, optionally followed by `ORDER BY` and `LIMIT`, filtering `table_shorthand`.
+pub fn check_where(fragment: &LitStr, table_shorthand: &LitStr) -> Result<()> {
+    check(
+        fragment,
+        &format!(
+            "SELECT 1 FROM t AS {} WHERE {}",
+            table_shorthand.value(),
+            fragment.value()
+        ),
+    )
+}
+
+/// A single expression, e.g. `#[expr("...")]`.
+pub fn check_expr(fragment: &LitStr) -> Result<()> {
+    check(fragment, &format!("SELECT ({})", fragment.value()))
+}
+
+/// The `ResultKind` of `#[expr("...")]`'s result when the expression's form decides it: literals,
+/// comparisons and other boolean operators, `||` and `CAST`s. Anything else, e.g. a column, a
+/// function call or arithmetic, takes its type from values only SQLite knows.
+pub fn expr_kind(fragment: &LitStr) -> Option<&'static str> {
+    let expr = Parser::new(&SQLiteDialect {})
+        .try_with_sql(&fragment.value())
+        .and_then(|mut parser| parser.parse_expr())
+        .ok()?;
+    kind_of(&expr)
+}
+
+fn kind_of(expr: &Expr) -> Option<&'static str> {
+    match expr {
+        Expr::Nested(expr) => kind_of(expr),
+        Expr::Value(value) => match &value.value {
+            Value::Number(n, _) if n.starts_with("0x") || !n.contains(['.', 'e', 'E']) => {
+                Some("Integer")
+            }
+            Value::Number(..) => Some("Real"),
+            Value::SingleQuotedString(_) => Some("Text"),
+            Value::HexStringLiteral(_) => Some("Blob"),
+            Value::Boolean(_) => Some("Integer"),
+            _ => None,
+        },
+        Expr::BinaryOp { op, .. } => match op {
+            BinaryOperator::Eq
+            | BinaryOperator::NotEq
+            | BinaryOperator::Lt
+            | BinaryOperator::LtEq
+            | BinaryOperator::Gt
+            | BinaryOperator::GtEq
+            | BinaryOperator::And
+            | BinaryOperator::Or => Some("Integer"),
+            BinaryOperator::StringConcat => Some("Text"),
+            _ => None,
+        },
+        Expr::UnaryOp {
+            op: UnaryOperator::Not,
+            ..
+        }
+        | Expr::IsNull(_)
+        | Expr::IsNotNull(_)
+        | Expr::IsTrue(_)
+        | Expr::IsNotTrue(_)
+        | Expr::IsFalse(_)
+        | Expr::IsNotFalse(_)
+        | Expr::IsDistinctFrom(..)
+        | Expr::IsNotDistinctFrom(..)
+        | Expr::InList { .. }
+        | Expr::InSubquery { .. }
+        | Expr::Between { .. }
+        | Expr::Like { .. }
+        | Expr::Exists { .. } => Some("Integer"),
+        // SQLite's affinity rules, as `DbType::strict_db_type`; `NUMERIC` may give either number
+        Expr::Cast { data_type, .. } => {
+            let data_type = data_type.to_string().to_uppercase();
+            if data_type.contains("INT") {
+                Some("Integer")
+            } else if ["CHAR", "CLOB", "TEXT"]
+                .iter()
+                .any(|t| data_type.contains(t))
+            {
+                Some("Text")
+            } else if data_type.contains("BLOB") {
+                Some("Blob")
+            } else if ["REAL", "FLOA", "DOUB"]
+                .iter()
+                .any(|t| data_type.contains(t))
+            {
+                Some("Real")
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,46 @@
+use syn::{
+    Result,
+    parse::{Parse, ParseStream},
+};
+
+mod kw {
+    syn::custom_keyword!(text);
+    syn::custom_keyword!(blob);
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum StoreAs {
+    Text,
+    Blob,
+}
+
+impl StoreAs {
+    pub fn db_type(&self) -> &'static str {
+        match self {
+            StoreAs::Text => "TEXT",
+            StoreAs::Blob => "BLOB",
+        }
+    }
+
+    pub fn wrapper(&self) -> proc_macro2::TokenStream {
+        match self {
+            StoreAs::Text => quote::quote! { AsText },
+            StoreAs::Blob => quote::quote! { AsBlob },
+        }
+    }
+}
+
+impl Parse for StoreAs {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::text) {
+            input.parse::<kw::text>()?;
+            Ok(StoreAs::Text)
+        } else if lookahead.peek(kw::blob) {
+            input.parse::<kw::blob>()?;
+            Ok(StoreAs::Blob)
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
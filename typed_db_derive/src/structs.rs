@@ -1,9 +1,26 @@
 use std::{borrow::Cow, collections::HashMap};
 
-use quote::{ToTokens, quote};
+use quote::{ToTokens, quote, quote_spanned};
 use syn::{Result, parse::Parse, spanned::Spanned};
 
-use crate::{default_value_parser::*, foreign_key_parser::ForeignKeyAttr};
+use crate::{
+    default_value_parser::*, foreign_key_parser::ForeignKeyAttr,
+    generated_column_parser::GeneratedColumn, store_as_parser::StoreAs,
+    table_options_parser::TableOptions, validate_parser::ValidateAttr,
+};
+
+mod kw {
+    syn::custom_keyword!(autoincrement);
+    syn::custom_keyword!(trigger);
+}
+
+/// `#[created_at]`, or `#[updated_at]` which `#[updated_at(trigger)]` also refreshes on updates
+/// through raw SQL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampAttr {
+    CreatedAt,
+    UpdatedAt { trigger: bool },
+}
 
 pub struct TableFieldInfo {
     pub visibility: syn::Visibility,
@@ -13,37 +30,296 @@ pub struct TableFieldInfo {
 }
 
 impl TableFieldInfo {
-    pub fn as_txt(&self) -> Result<proc_macro2::TokenStream> {
+    pub fn as_txt(&self, strict: bool) -> Result<proc_macro2::TokenStream> {
         // SQLite format creation string
         let field_name = &self.name;
         let ty = &self.ty;
+        let db_type = if self.is_autoincrement()? {
+            // AUTOINCREMENT is only allowed on a column declared exactly `INTEGER`
+            quote! { "INTEGER" }
+        } else if strict {
+            quote! { <#ty as DbType>::strict_db_type() }
+        } else {
+            quote! { <#ty as DbType>::db_type() }
+        };
 
         let constraints = self.column_constraints()?;
-        let out = quote! {
-            format!(stringify!(#field_name {} {}), <#ty as DbType>::db_type(), #constraints)
+        let out = match self.store_as()? {
+            Some(store_as) => {
+                let db_type = store_as.db_type();
+                quote! {
+                    format!(stringify!(#field_name {} {}), #db_type, #constraints)
+                }
+            }
+            None => quote! {
+                format!(
+                    concat!(stringify!(#field_name), " {} {}{}"),
+                    #db_type,
+                    #constraints,
+                    if <#ty as DbType>::UNSIGNED {
+                        concat!(" CHECK (", stringify!(#field_name), " >= 0)")
+                    } else {
+                        ""
+                    }
+                )
+            },
+        };
+        Ok(out)
+    }
+
+    pub fn store_as(&self) -> Result<Option<StoreAs>> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("store_as"))
+            .collect::<Vec<_>>();
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].span(),
+                "Only one store_as attribute allowed per field",
+            ));
+        }
+        attrs.into_iter().next().map(|a| a.parse_args()).transpose()
+    }
+
+    /// Expression converting the field value into something bindable.
+    fn to_sql_value(&self, value: &proc_macro2::Ident) -> Result<proc_macro2::TokenStream> {
+        let out = match self.store_as()? {
+            Some(store_as) => {
+                let wrapper = store_as.wrapper();
+                if self.is_optional() {
+                    quote! { #value.map(#wrapper) }
+                } else {
+                    quote! { #wrapper(#value) }
+                }
+            }
+            None => quote! { #value },
+        };
+        Ok(out)
+    }
+
+    /// [`Self::to_sql_value`] for a reference to the field. Blobs are encoded from a clone, as
+    /// `AsBlob` needs the value itself.
+    fn to_sql_ref(&self, value: &proc_macro2::Ident) -> Result<proc_macro2::TokenStream> {
+        let out = match self.store_as()? {
+            Some(StoreAs::Blob) if self.is_optional() => quote! { #value.clone().map(AsBlob) },
+            Some(StoreAs::Blob) => quote! { AsBlob(#value.clone()) },
+            Some(StoreAs::Text) if self.is_optional() => quote! { #value.as_ref().map(AsText) },
+            Some(StoreAs::Text) => quote! { AsText(#value) },
+            None => quote! { #value },
+        };
+        Ok(out)
+    }
+
+    /// The field of `receiver` as a checked `&dyn ToSql` parameter.
+    fn bind_ref(&self, receiver: &proc_macro2::Ident) -> proc_macro2::TokenStream {
+        let fname = &self.name;
+        let fname_str = self.name.to_string();
+        let value = self
+            .to_sql_ref(fname)
+            .unwrap_or_else(|e| e.to_compile_error());
+        quote! {
+            &CheckedValue {
+                table: <Self as DbTable>::TABLE_NAME,
+                column: #fname_str,
+                value: {
+                    let #fname = &#receiver.#fname;
+                    #value
+                },
+            } as &dyn rusqlite::ToSql
+        }
+    }
+
+    /// Expression reading the field out of column `i` of a row.
+    fn row_value(&self, i: usize) -> Result<proc_macro2::TokenStream> {
+        let out = match self.store_as()? {
+            Some(store_as) => {
+                let wrapper = store_as.wrapper();
+                if self.is_optional() {
+                    quote! { row.get::<_, Option<#wrapper<_>>>(#i)?.map(|v| v.0) }
+                } else {
+                    quote! { row.get::<_, #wrapper<_>>(#i)?.0 }
+                }
+            }
+            None => quote! { row.get(#i)? },
         };
         Ok(out)
     }
 
-    fn is_primary_key(&self) -> bool {
+    pub fn is_primary_key(&self) -> bool {
         self.attributes
             .iter()
             .any(|attr| attr.path().is_ident("primary_key"))
     }
 
-    fn is_composite_key(&self) -> bool {
+    pub fn is_composite_key(&self) -> bool {
         self.attributes
             .iter()
             .any(|attr| attr.path().is_ident("composite_key"))
     }
 
-    fn primary_key_text(&self) -> &str {
+    pub fn is_autoincrement(&self) -> Result<bool> {
+        // `#[primary_key(autoincrement)]`
+        for attr in self.attributes.iter() {
+            if attr.path().is_ident("primary_key") && matches!(attr.meta, syn::Meta::List(_)) {
+                attr.parse_args::<kw::autoincrement>()?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn primary_key_text(&self) -> Result<&str> {
         // Check for an attribute to the field called `primary_key`
-        if self.is_primary_key() {
+        let text = if self.is_autoincrement()? {
+            "PRIMARY KEY AUTOINCREMENT"
+        } else if self.is_primary_key() {
             "PRIMARY KEY"
         } else {
             ""
+        };
+        Ok(text)
+    }
+
+    fn collate_text(&self) -> Result<String> {
+        // Check for an attribute to the field called `collate(NAME)`
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("collate"))
+            .collect::<Vec<_>>();
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].span(),
+                "Only one collate attribute allowed per field",
+            ));
+        }
+        let ret = match attrs.into_iter().next() {
+            Some(attr) => format!("COLLATE {}", attr.parse_args::<syn::Ident>()?),
+            None => String::new(),
+        };
+        Ok(ret)
+    }
+
+    pub fn generated(&self) -> Result<Option<GeneratedColumn>> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("generated"))
+            .collect::<Vec<_>>();
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].span(),
+                "Only one generated attribute allowed per field",
+            ));
+        }
+        attrs.into_iter().next().map(|a| a.parse_args()).transpose()
+    }
+
+    /// `#[default_fn(path)]`, a function computing the value when the builder doesn't set it.
+    pub fn default_fn(&self) -> Result<Option<syn::Path>> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("default_fn"))
+            .collect::<Vec<_>>();
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].span(),
+                "Only one default_fn attribute allowed per field",
+            ));
+        }
+        attrs.into_iter().next().map(|a| a.parse_args()).transpose()
+    }
+
+    /// Fields the builder must set before it can insert, i.e. NOT NULL columns without a default.
+    /// Primary keys are only left to SQLite when they alias the rowid, see
+    /// [`TableInfo::is_required`].
+    fn is_required(&self) -> bool {
+        !(self.is_optional()
+            || self.is_primary_key()
+            || self.is_generated()
+            || self.attributes.iter().any(|attr| {
+                [
+                    "default",
+                    "default_fn",
+                    "created_at",
+                    "updated_at",
+                    "version",
+                ]
+                .iter()
+                .any(|name| attr.path().is_ident(name))
+            }))
+    }
+
+    pub fn is_version(&self) -> bool {
+        self.attributes
+            .iter()
+            .any(|attr| attr.path().is_ident("version"))
+    }
+
+    pub fn is_soft_delete(&self) -> bool {
+        self.attributes
+            .iter()
+            .any(|attr| attr.path().is_ident("soft_delete"))
+    }
+
+    /// `#[created_at]` or `#[updated_at]`, set from the clock on insert.
+    pub fn timestamp(&self) -> Result<Option<TimestampAttr>> {
+        let attrs = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("created_at") || attr.path().is_ident("updated_at"))
+            .collect::<Vec<_>>();
+        if attrs.len() > 1 {
+            return Err(syn::Error::new(
+                attrs[1].span(),
+                "Only one created_at or updated_at attribute allowed per field",
+            ));
+        }
+        let attr = match attrs.into_iter().next() {
+            Some(attr) => attr,
+            None => return Ok(None),
+        };
+        if attr.path().is_ident("created_at") {
+            attr.meta.require_path_only()?;
+            return Ok(Some(TimestampAttr::CreatedAt));
         }
+        let trigger = match &attr.meta {
+            syn::Meta::List(_) => {
+                attr.parse_args::<kw::trigger>()?;
+                true
+            }
+            meta => {
+                meta.require_path_only()?;
+                false
+            }
+        };
+        Ok(Some(TimestampAttr::UpdatedAt { trigger }))
+    }
+
+    /// The builder's type parameter tracking whether a required field has been set.
+    pub fn state_param(&self) -> syn::Ident {
+        let camel = self
+            .name
+            .to_string()
+            .split('_')
+            .map(|part| {
+                let mut chars = part.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                    .unwrap_or_default()
+            })
+            .collect::<String>();
+        syn::Ident::new(&format!("__{camel}"), self.name.span())
+    }
+
+    /// Generated columns are computed by SQLite, so they can't be inserted.
+    pub fn is_generated(&self) -> bool {
+        self.attributes
+            .iter()
+            .any(|attr| attr.path().is_ident("generated"))
     }
 
     fn unique_text(&self) -> &str {
@@ -59,8 +335,7 @@ impl TableFieldInfo {
         }
     }
 
-    fn default_text(&self) -> Result<String> {
-        // Check for an attribute to the field called `default("value")`
+    fn default_attr(&self) -> Result<Option<(&syn::Attribute, DefaultValues)>> {
         let attrs = self
             .attributes
             .iter()
@@ -72,16 +347,88 @@ impl TableFieldInfo {
                 "Only one default attribute allowed per field",
             ));
         }
-        let attr = attrs.into_iter().next();
-        let ret = if let Some(attr) = attr {
-            let val: DefaultValues = attr.parse_args()?;
-            format!("DEFAULT {val}")
-        } else {
-            "".to_string()
+        attrs
+            .into_iter()
+            .next()
+            .map(|attr| Ok((attr, attr.parse_args()?)))
+            .transpose()
+    }
+
+    fn default_text(&self) -> Result<String> {
+        // Check for an attribute to the field called `default("value")`
+        let ret = match self.default_attr()? {
+            Some((_, val)) => format!("DEFAULT {val}"),
+            None => "".to_string(),
         };
         Ok(ret)
     }
 
+    /// Compile time check that the `#[default(...)]` value suits the field's type.
+    pub fn default_check(&self) -> Result<proc_macro2::TokenStream> {
+        let (attr, val) = match self.default_attr()? {
+            Some(default) => default,
+            None => return Ok(quote! {}),
+        };
+        if val.is_null() && !self.is_optional() {
+            return Err(syn::Error::new(
+                attr.span(),
+                "`#[default(NULL)]` requires an `Option<_>` field",
+            ));
+        }
+        // Stored values are encoded, so the field type says nothing about the column type
+        if self.store_as()?.is_some() {
+            return Ok(quote! {});
+        }
+        let kind = match val.kind() {
+            Some(kind) => syn::Ident::new(kind, attr.span()),
+            None => return Ok(quote! {}),
+        };
+        let ty = &self.ty;
+        let field_name = &self.name;
+        let negative_check = if val.is_negative() {
+            quote_spanned! {attr.span()=>
+                const _: () = assert!(
+                    !<#ty as DbType>::UNSIGNED,
+                    concat!("negative `#[default(...)]` for unsigned field `", stringify!(#field_name), "`"),
+                );
+            }
+        } else {
+            quote! {}
+        };
+        Ok(quote_spanned! {attr.span()=>
+            const _: () = assert!(
+                DefaultKind::#kind.allowed_in(<#ty as DbType>::DEFAULTS),
+                concat!("`#[default(...)]` doesn't match the type of field `", stringify!(#field_name), "`"),
+            );
+            #negative_check
+        })
+    }
+
+    /// Whether the primary key may alias the rowid, judging by its type: `Some(true)` for integer
+    /// types declared `INTEGER`, `Some(false)` for other known types and `None` for the rest,
+    /// like type aliases, which `DbType::ROWID_ALIAS` decides.
+    fn rowid_alias_type(&self, strict: bool) -> Option<bool> {
+        if self.is_autoincrement().unwrap_or(false) {
+            return Some(true);
+        }
+        if self.store_as().ok().flatten().is_some() {
+            return Some(false);
+        }
+        let syn::Type::Path(path) = &self.ty else {
+            return Some(false);
+        };
+        let ident = path.path.segments.last()?.ident.to_string();
+        let alias = match ident.as_str() {
+            "i32" | "isize" | "u32" | "usize" => true,
+            // Every integer is declared `INTEGER` in strict tables
+            "i8" | "u8" | "i16" | "u16" | "i64" | "u64" => strict,
+            "String" | "str" | "PathBuf" | "OsString" | "f32" | "f64" | "bool" | "Vec"
+            | "DateTime" | "NaiveDateTime" | "NaiveDate" | "NaiveTime" => false,
+            _ => return None,
+        };
+        Some(alias)
+    }
+
     fn is_optional(&self) -> bool {
         match &self.ty {
             syn::Type::Path(path) => path
@@ -93,6 +440,24 @@ impl TableFieldInfo {
         }
     }
 
+    /// `T` of an `Option<T>` field.
+    fn option_inner(&self) -> Option<&syn::Type> {
+        let syn::Type::Path(path) = &self.ty else {
+            return None;
+        };
+        let segment = path.path.segments.last()?;
+        if segment.ident != "Option" {
+            return None;
+        }
+        match &segment.arguments {
+            syn::PathArguments::AngleBracketed(args) => match args.args.first()? {
+                syn::GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     fn foreign_key(&self) -> Result<Option<ForeignKeyAttr>> {
         let foreign_keys = self
             .attributes
@@ -117,13 +482,109 @@ impl TableFieldInfo {
         Ok(fk_attr)
     }
 
+    /// The `#[validate(...)]` attributes, a field may have several.
+    pub fn validate_attrs(&self) -> Result<Vec<ValidateAttr>> {
+        self.attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("validate"))
+            .map(|attr| attr.parse_args())
+            .collect()
+    }
+
+    /// The `CHECK` constraint of the `#[validate(..., check)]` rules.
+    fn validate_check_text(&self) -> Result<String> {
+        let column = self.name.to_string();
+        let mut checks = Vec::new();
+        for (attr, validate) in self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("validate"))
+            .zip(self.validate_attrs()?)
+        {
+            if !validate.check {
+                continue;
+            }
+            if self.store_as()?.is_some() {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "`check` can't be used on `#[store_as(...)]` fields",
+                ));
+            }
+            let len = checks.len();
+            checks.extend(validate.rules.iter().filter_map(|r| r.check_sql(&column)));
+            if checks.len() == len {
+                return Err(syn::Error::new(
+                    attr.span(),
+                    "`check` requires a `length` or `range` rule",
+                ));
+            }
+        }
+        let ret = match checks.is_empty() {
+            true => String::new(),
+            false => format!("CHECK ({})", checks.join(" AND ")),
+        };
+        Ok(ret)
+    }
+
+    /// Statements checking the `#[validate(...)]` rules against the field's parameter of the
+    /// generated `validate_fields`, an `Option<&T>` that is `None` when it isn't set.
+    pub fn validation(&self) -> Result<Option<proc_macro2::TokenStream>> {
+        let field = self.name.to_string();
+        let field = field.as_str();
+        let rules = self
+            .attributes
+            .iter()
+            .filter(|attr| attr.path().is_ident("validate"))
+            .zip(self.validate_attrs()?)
+            .flat_map(|(attr, validate)| {
+                validate.rules.into_iter().map(move |rule| {
+                    let rule = rule.to_validation(field);
+                    quote_spanned! {attr.span()=> { #rule } }
+                })
+            })
+            .collect::<Vec<_>>();
+        if rules.is_empty() {
+            return Ok(None);
+        }
+        let name = &self.name;
+        let out = if self.is_optional() {
+            quote! {
+                if let Some(Some(value)) = #name {
+                    #(#rules)*
+                }
+            }
+        } else {
+            quote! {
+                if let Some(value) = #name {
+                    #(#rules)*
+                }
+            }
+        };
+        Ok(Some(out))
+    }
+
     fn column_constraints(&self) -> Result<String> {
+        let generated = match self.generated()? {
+            Some(generated) => {
+                if self.is_primary_key() || !self.default_text()?.is_empty() {
+                    return Err(syn::Error::new(
+                        self.name.span(),
+                        "Generated columns can't be a primary key or have a default",
+                    ));
+                }
+                generated.to_string()
+            }
+            None => String::new(),
+        };
         let optional_text = if self.is_optional() { "" } else { "NOT NULL" };
         let optional_text = [
             optional_text,
-            self.primary_key_text(),
+            self.primary_key_text()?,
             self.unique_text(),
             self.default_text()?.as_str(),
+            self.collate_text()?.as_str(),
+            generated.as_str(),
+            self.validate_check_text()?.as_str(),
         ]
         .into_iter()
         .filter(|s| !s.is_empty())
@@ -136,7 +597,6 @@ impl TableFieldInfo {
 pub struct TableInfo {
     pub name: syn::Ident,
     pub fields: Vec<TableFieldInfo>,
-    #[allow(dead_code)]
     pub attributes: Vec<syn::Attribute>,
 }
 
@@ -154,11 +614,182 @@ impl TableInfo {
             .collect::<Vec<_>>()
             .join(sep)
     }
+    pub fn options(&self) -> Result<TableOptions> {
+        TableOptions::from_attributes(&self.attributes)
+    }
+
+    /// Fields that can be set through the builder.
+    pub fn insertable_fields(&self) -> impl Iterator<Item = &TableFieldInfo> {
+        self.fields.iter().filter(|f| !f.is_generated())
+    }
+
+    /// The `#[primary_key]` field or the `#[composite_key]` fields.
+    pub fn key_fields(&self) -> Vec<&TableFieldInfo> {
+        self.fields
+            .iter()
+            .filter(|f| f.is_primary_key() || f.is_composite_key())
+            .collect()
+    }
+
+    /// `#[hooks]`, running the table's `DbTableHooks` around inserts, updates and deletes.
+    pub fn has_hooks(&self) -> bool {
+        self.attributes
+            .iter()
+            .any(|attr| attr.path().is_ident("hooks"))
+    }
+
+    /// `#[history]`, keeping every version of the rows in a `<Table>History` table.
+    pub fn has_history(&self) -> bool {
+        self.attributes
+            .iter()
+            .any(|attr| attr.path().is_ident("history"))
+    }
+
+    /// The `#[soft_delete]` field, the `Option` of a timestamp set instead of deleting the row.
+    pub fn soft_delete_field(&self) -> Result<Option<&TableFieldInfo>> {
+        let fields = self
+            .fields
+            .iter()
+            .filter(|f| f.is_soft_delete())
+            .collect::<Vec<_>>();
+        if fields.len() > 1 {
+            return Err(syn::Error::new(
+                fields[1].name.span(),
+                "Only one soft_delete field allowed per table",
+            ));
+        }
+        let field = match fields.into_iter().next() {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        if field.option_inner().is_none() {
+            return Err(syn::Error::new(
+                field.ty.span(),
+                "`#[soft_delete]` requires an `Option<_>` field",
+            ));
+        }
+        Ok(Some(field))
+    }
+
+    /// The `#[version]` field, incremented by every update and checked to still be the one read.
+    pub fn version_field(&self) -> Result<Option<&TableFieldInfo>> {
+        let fields = self
+            .fields
+            .iter()
+            .filter(|f| f.is_version())
+            .collect::<Vec<_>>();
+        if fields.len() > 1 {
+            return Err(syn::Error::new(
+                fields[1].name.span(),
+                "Only one version field allowed per table",
+            ));
+        }
+        let field = match fields.into_iter().next() {
+            Some(field) => field,
+            None => return Ok(None),
+        };
+        if field.is_optional() || field.is_primary_key() || field.is_composite_key() {
+            return Err(syn::Error::new(
+                field.name.span(),
+                "`#[version]` must be a non-key integer field",
+            ));
+        }
+        Ok(Some(field))
+    }
+
+    /// `SoftDelete::ROW_KEY`, the key columns of `WITHOUT ROWID` tables and `ROWID` otherwise.
+    fn row_key(&self) -> Result<String> {
+        let key = if self.options()?.without_rowid {
+            self.key_fields()
+                .iter()
+                .map(|f| f.name.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        } else {
+            "ROWID".to_string()
+        };
+        Ok(key)
+    }
+
+    /// The `DbTable::SELECT_FROM` and `SoftDelete` impl of `#[soft_delete]` tables.
+    fn impl_soft_delete(&self) -> Result<(proc_macro2::TokenStream, proc_macro2::TokenStream)> {
+        let field = match self.soft_delete_field()? {
+            Some(field) => field,
+            None => return Ok((quote! {}, quote! {})),
+        };
+        let name = &self.name;
+        let deleted_at = field.name.to_string();
+        let deleted_ty = field.option_inner();
+        let row_key = self.row_key()?;
+        let rowid = if row_key == "ROWID" {
+            "ROWID AS ROWID, "
+        } else {
+            ""
+        };
+        let select_from =
+            format!("(SELECT {rowid}* FROM {name} WHERE {deleted_at} IS NULL) AS {name}");
+        let select_from = quote! {
+            const SELECT_FROM: &'static str = #select_from;
+        };
+        let soft_delete = quote! {
+            #[automatically_derived]
+            impl SoftDelete for #name {
+                type DeletedAt = #deleted_ty;
+                const DELETED_AT: &'static str = #deleted_at;
+                const ROW_KEY: &'static str = #row_key;
+            }
+        };
+        Ok((select_from, soft_delete))
+    }
+
+    /// [`TableFieldInfo::is_required`], plus primary keys that don't alias the rowid, which
+    /// SQLite can't assign.
+    pub fn is_required(&self, f: &TableFieldInfo) -> bool {
+        if f.is_primary_key() && !f.is_optional() {
+            let options = self.options().unwrap_or_default();
+            return options.without_rowid || f.rowid_alias_type(options.strict) == Some(false);
+        }
+        f.is_required()
+    }
+
+    /// For primary keys left to SQLite whose type the derive can't judge, a check that the type
+    /// is declared `INTEGER`.
+    fn rowid_alias_check(&self) -> proc_macro2::TokenStream {
+        let options = self.options().unwrap_or_default();
+        let checks = self
+            .fields
+            .iter()
+            .filter(|f| f.is_primary_key() && !f.is_optional() && !self.is_required(f))
+            .filter(|f| f.rowid_alias_type(options.strict).is_none())
+            .map(|f| {
+                let ty = &f.ty;
+                let message = format!(
+                    "`{}` is the primary key but its type isn't declared INTEGER, so SQLite can't assign it",
+                    f.name
+                );
+                quote! { assert!(<#ty as DbType>::ROWID_ALIAS, #message); }
+            });
+        quote! { const _: () = { #(#checks)* }; }
+    }
+
     pub fn builder_name(&self) -> syn::Ident {
         let name = &self.name;
         syn::Ident::new((name.to_string() + "Builder").as_str(), name.span())
     }
 
+    /// The tables referenced by `#[foreign_key]`s, each once.
+    pub fn foreign_tables(&self) -> Result<Vec<syn::Type>> {
+        let mut tables = Vec::<syn::Type>::new();
+        for f in self.fields.iter() {
+            if let Some(fk) = f.foreign_key()?
+                && !tables.contains(&fk.table)
+            {
+                tables.push(fk.table);
+            }
+        }
+        Ok(tables)
+    }
+
     pub fn foreign_keys(&self) -> Result<Vec<String>> {
         let mut foreign_tables = HashMap::<_, Vec<_>>::new();
         for f in self.fields.iter() {
@@ -199,10 +830,18 @@ impl TableInfo {
     }
 
     pub fn creation_str(&self) -> proc_macro2::TokenStream {
-        let data = self
+        let options = match self.options() {
+            Ok(options) => options,
+            Err(e) => return e.to_compile_error(),
+        };
+        let data = self.fields.iter().map(|f| {
+            f.as_txt(options.strict)
+                .unwrap_or_else(|e| e.to_compile_error())
+        });
+        let default_checks = self
             .fields
             .iter()
-            .map(|f| f.as_txt().unwrap_or_else(|e| e.to_compile_error()));
+            .map(|f| f.default_check().unwrap_or_else(|e| e.to_compile_error()));
 
         let composite_keys = self
             .fields
@@ -238,13 +877,39 @@ impl TableInfo {
             .to_compile_error();
         }
 
-        let composite_keys = if composite_keys.len() > 0 {
-            quote! { stringify!(PRIMARY KEY (#(#composite_keys),*) )}
-        } else {
-            quote! {""}
+        if options.without_rowid {
+            for f in self.fields.iter() {
+                match f.is_autoincrement() {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        return syn::Error::new(
+                            f.name.span(),
+                            "AUTOINCREMENT is not allowed on `#[table(without_rowid)]` tables",
+                        )
+                        .to_compile_error();
+                    }
+                    Err(e) => return e.to_compile_error(),
+                }
+            }
+        }
+
+        if options.without_rowid && self.key_fields().is_empty() {
+            return syn::Error::new(
+                self.name.span(),
+                "`#[table(without_rowid)]` requires a `#[primary_key]` or `#[composite_key]`",
+            )
+            .to_compile_error();
+        }
+        let table_options = options.suffix();
+
+        let composite_keys = if composite_keys.len() > 0 {
+            quote! { stringify!(PRIMARY KEY (#(#composite_keys),*) )}
+        } else {
+            quote! {""}
         };
 
         quote! {
+            #(#default_checks)*
             let mut lines = vec![#(#data),*];
             let foreign_keys = vec![#(#foreign_keys.to_string()),*];
             if !#composite_keys.is_empty() {
@@ -254,78 +919,401 @@ impl TableInfo {
             format!(
                 "CREATE TABLE IF NOT EXISTS {} (
     {}
-)",
+){}",
                 Self::TABLE_NAME,
                 lines.join(",\n    "),
+                #table_options,
             )
         }
     }
 
+    /// `create_triggers_str`, for tables with `#[updated_at(trigger)]` fields or `#[history]`, and
+    /// `drop_table` for the former.
+    fn triggers_str(&self) -> Result<proc_macro2::TokenStream> {
+        let mut statements = self.updated_at_triggers()?;
+        if self.has_history() {
+            statements.push(quote! { <Self as DbHistory>::create_history_str() });
+        }
+        if statements.is_empty() {
+            return Ok(quote! {});
+        }
+        // The `<Table>Updating` table goes with the table, unlike the history
+        let drop_table = if self.has_updated_at_trigger()? {
+            let drop = format!("DROP TABLE IF EXISTS {}", self.name);
+            let drop_updating = format!("DROP TABLE IF EXISTS {}Updating", self.name);
+            quote! {
+                fn drop_table(conn: &rusqlite::Connection) -> ::core::result::Result<usize, ::typed_db::Error> {
+                    let dropped = conn.execute(#drop, ())?;
+                    conn.execute(#drop_updating, ())?;
+                    Ok(dropped)
+                }
+            }
+        } else {
+            quote! {}
+        };
+        Ok(quote! {
+            fn create_triggers_str() -> String {
+                [#(#statements),*].join(";\n")
+            }
+            #drop_table
+        })
+    }
+
+    /// Whether a field is `#[updated_at(trigger)]`.
+    fn has_updated_at_trigger(&self) -> Result<bool> {
+        for f in self.fields.iter() {
+            if f.timestamp()? == Some(TimestampAttr::UpdatedAt { trigger: true }) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The `#[updated_at(trigger)]` triggers, which set the field when an update leaves it as is.
+    /// `update` holds a row in the `<Table>Updating` table while it writes, so the triggers don't
+    /// replace the time it set when the clock didn't move.
+    fn updated_at_triggers(&self) -> Result<Vec<proc_macro2::TokenStream>> {
+        if !self.has_updated_at_trigger()? {
+            return Ok(Vec::new());
+        }
+        let key_fields = self.key_fields();
+        let row_condition = if key_fields.is_empty() {
+            "ROWID = NEW.ROWID".to_string()
+        } else {
+            key_fields
+                .iter()
+                .map(|f| format!("{0} = NEW.{0}", f.name))
+                .collect::<Vec<_>>()
+                .join(" AND ")
+        };
+        let table = &self.name;
+        let updating = format!("CREATE TABLE IF NOT EXISTS {table}Updating (updating INTEGER)");
+        let mut triggers = vec![quote! { #updating.to_string() }];
+        for f in self.fields.iter() {
+            if f.timestamp()? != Some(TimestampAttr::UpdatedAt { trigger: true }) {
+                continue;
+            }
+            let field = &f.name;
+            let ty = &f.ty;
+            let sql = format!(
+                "CREATE TRIGGER IF NOT EXISTS {table}_{field} AFTER UPDATE ON {table} FOR EACH ROW \
+                WHEN NEW.{field} IS OLD.{field} AND NOT EXISTS (SELECT 1 FROM {table}Updating) \
+                BEGIN UPDATE {table} SET {field} = {{}} WHERE {row_condition}; END"
+            );
+            triggers.push(quote! { format!(#sql, <#ty as Timestamp>::SQL_NOW) });
+        }
+        Ok(triggers)
+    }
+
+    /// The `DbHistory` impl of `#[history]` tables.
+    fn impl_history(&self) -> Result<proc_macro2::TokenStream> {
+        if !self.has_history() {
+            return Ok(quote! {});
+        }
+        let name = &self.name;
+        let key_fields = self.key_fields();
+        if key_fields.is_empty() {
+            return Err(syn::Error::new(
+                name.span(),
+                "`#[history]` requires a `#[primary_key]` or `#[composite_key]`",
+            ));
+        }
+        let key_names = key_fields.iter().map(|f| &f.name).collect::<Vec<_>>();
+        let key_tys = key_fields.iter().map(|f| &f.ty);
+        let key_columns = key_fields.iter().map(|f| f.name.to_string());
+        let (key_ty, key_pattern) = if key_fields.len() == 1 {
+            (quote! { #(#key_tys)* }, quote! { #(#key_names)* })
+        } else {
+            (quote! { (#(#key_tys),*) }, quote! { (#(#key_names),*) })
+        };
+        let params = key_fields
+            .iter()
+            .map(|f| {
+                let column = f.name.to_string();
+                let value = f.to_sql_ref(&f.name)?;
+                Ok(quote! {
+                    Box::new(CheckedValue {
+                        table: <Self as DbTable>::TABLE_NAME,
+                        column: #column,
+                        value: #value,
+                    }) as Box<dyn rusqlite::ToSql + '_>
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(quote! {
+            #[automatically_derived]
+            impl DbHistory for #name {
+                type Key = #key_ty;
+                const KEY_COLUMNS: &'static [&'static str] = &[#(#key_columns),*];
+                fn key_params(key: &Self::Key) -> Vec<Box<dyn rusqlite::ToSql + '_>> {
+                    let #key_pattern = key;
+                    vec![#(#params),*]
+                }
+            }
+        })
+    }
+
     pub fn impl_dtable_str(&self) -> proc_macro2::TokenStream {
         let name = &self.name;
         let creation_str = self.creation_str();
+        let triggers_str = self.triggers_str().unwrap_or_else(|e| e.to_compile_error());
         let column_names = self.fields_str();
         let select_where = self.impl_select_where();
+        let (select_from, soft_delete) = match self.impl_soft_delete() {
+            Ok(soft_delete) => soft_delete,
+            Err(err) => return err.to_compile_error(),
+        };
+        let delete = if self.has_hooks() {
+            self.impl_delete_hooked()
+        } else if !soft_delete.is_empty() {
+            quote! {
+                #[automatically_derived]
+                fn delete(
+                    conn: &rusqlite::Connection,
+                    where_clause: &str,
+                    params: impl rusqlite::Params,
+                ) -> ::core::result::Result<usize, ::typed_db::Error> {
+                    <Self as SoftDelete>::soft_delete(conn, where_clause, params)
+                }
+
+                #[automatically_derived]
+                fn explain_delete(
+                    conn: &rusqlite::Connection,
+                    where_clause: &str,
+                    params: impl rusqlite::Params,
+                ) -> ::core::result::Result<QueryPlan, ::typed_db::Error> {
+                    let sql = <Self as SoftDelete>::soft_delete_str(where_clause)?;
+                    QueryPlan::explain(conn, &sql, params)
+                }
+            }
+        } else {
+            quote! {}
+        };
+        let history = match self.impl_history() {
+            Ok(history) => history,
+            Err(err) => return err.to_compile_error(),
+        };
+        let without_rowid = match self.options() {
+            Ok(options) => options.without_rowid,
+            Err(err) => return err.to_compile_error(),
+        };
+        let rowid_check = self.rowid_alias_check();
+        let builder_name = self.builder_name();
+        let builder_states = self
+            .insertable_fields()
+            .filter(|f| self.is_required(f))
+            .map(|_| quote! { FieldSet });
+        let foreign_tables = match self.foreign_tables() {
+            Ok(tables) => tables,
+            Err(err) => return err.to_compile_error(),
+        };
+        let mut foreign_key_fields = Vec::new();
+        for f in self.fields.iter() {
+            match f.foreign_key() {
+                Ok(Some(_)) => foreign_key_fields.push(f.name.to_string()),
+                Ok(None) => {}
+                Err(err) => return err.to_compile_error(),
+            }
+        }
         quote! {
             #[automatically_derived]
             impl DbTable for #name {
+                type Builder = #builder_name<#(#builder_states),*>;
                 const TABLE_NAME: &'static str = stringify!(#name);
+                const FOREIGN_TABLES: &'static [&'static str] = &[#(<#foreign_tables as DbTable>::TABLE_NAME),*];
+                const FOREIGN_KEY_FIELDS: &'static [&'static str] = &[#(#foreign_key_fields),*];
+                const WITHOUT_ROWID: bool = #without_rowid;
+                #select_from
                 fn create_table_str() -> String {
                     #creation_str
                 }
+                #triggers_str
                 fn column_names() -> Box<[&'static str]> {
                     Box::new([#(#column_names),*])
                 }
                 #select_where
+                #delete
             }
+
+            #soft_delete
+            #history
+            #rowid_check
         }
     }
 
     pub fn impl_builder_str(&self) -> proc_macro2::TokenStream {
         let original_name = &self.name;
         let name = self.builder_name();
-        let full_types = self.fields.iter().map(|f| {
+        let full_types = self.insertable_fields().map(|f| {
             let field_name = &f.name;
             let ty = &f.ty;
             let vis = &f.visibility;
             quote! {#vis #field_name: ::std::option::Option<#ty>,}
         });
-        let with_fns = self.fields.iter().map(|f| {
+        let required = self
+            .insertable_fields()
+            .filter(|f| self.is_required(f))
+            .collect::<Vec<_>>();
+        let state_params = required.iter().map(|f| f.state_param()).collect::<Vec<_>>();
+        let field_names = self
+            .insertable_fields()
+            .map(|f| &f.name)
+            .collect::<Vec<_>>();
+        let with_fns = self.insertable_fields().map(|f| {
             let field_name = &f.name;
             let with_name = syn::Ident::new(&format!("with_{field_name}"), field_name.span());
             let ty = &f.ty;
-            quote! {#[automatically_derived] pub fn #with_name(mut self, #field_name: impl Into<#ty>) -> Self {self.#field_name = Some(#field_name.into()); self}}
+            if !self.is_required(f) {
+                return quote! {#[automatically_derived] pub fn #with_name(mut self, #field_name: impl Into<#ty>) -> Self {self.#field_name = Some(#field_name.into()); self}};
+            }
+            // Setting a required field changes its state parameter to `FieldSet`
+            let state = f.state_param();
+            let new_states = state_params.iter().map(|p| {
+                if *p == state {
+                    quote! { FieldSet }
+                } else {
+                    quote! { #p }
+                }
+            });
+            let moved_fields = field_names.iter().map(|n| {
+                if *n == field_name {
+                    quote! { #n: Some(#field_name.into()), }
+                } else {
+                    quote! { #n: self.#n, }
+                }
+            });
+            quote! {
+                #[automatically_derived]
+                pub fn #with_name(self, #field_name: impl Into<#ty>) -> #name<#(#new_states),*> {
+                    #name {
+                        #(#moved_fields)*
+                        _state: ::std::marker::PhantomData,
+                    }
+                }
+            }
         });
-        let build_str = self.fields.iter().map(|f| {
+        let options = match self.options() {
+            Ok(options) => options,
+            Err(e) => return e.to_compile_error(),
+        };
+        let build_str = self.insertable_fields().map(|f| {
             let fname = &f.name;
             let fname_str = f.name.to_string();
+            let value = f
+                .to_sql_value(fname)
+                .unwrap_or_else(|e| e.to_compile_error());
+            let ty = &f.ty;
+            let field = match (f.default_fn(), f.timestamp()) {
+                (Ok(Some(default_fn)), _) => {
+                    quote! { self.#fname.or_else(|| Some(#default_fn().into())) }
+                }
+                (Ok(None), Ok(Some(_))) => {
+                    quote! { self.#fname.or_else(|| Some(<#ty as Timestamp>::now())) }
+                }
+                (Ok(None), Ok(None)) if f.is_version() => {
+                    quote! { self.#fname.or_else(|| Some(<#ty as From<u8>>::from(1))) }
+                }
+                (Ok(None), Ok(None)) => quote! { self.#fname },
+                (Err(e), _) | (_, Err(e)) => e.to_compile_error(),
+            };
             quote! {
-                if let Some(#fname) = self.#fname {
+                if let Some(#fname) = #field {
                     fnames.push(#fname_str);
-                    values.push(Box::new(#fname));
+                    values.push(Box::new(CheckedValue {
+                        table: #original_name::TABLE_NAME,
+                        column: #fname_str,
+                        value: #value,
+                    }));
                 }
             }
         });
 
+        let (build_fns, build_ty) = if self.has_hooks() {
+            self.impl_build_hooked(options.without_rowid)
+        } else if options.without_rowid {
+            self.impl_build_returning()
+        } else {
+            let build_fns = quote! {
+                #[automatically_derived]
+                /// Inserts the row into the database and returns the [ROWID](https://www.sqlite.org/lang_createtable.html#rowid)
+                pub fn build(self, conn: &::rusqlite::Connection) -> ::core::result::Result<i64, ::typed_db::Error> {
+                    self.build_raw(&conn)?;
+                    Ok(conn.last_insert_rowid())
+                }
+
+                #[automatically_derived]
+                /// Inserts and returns the new object with all data from the db
+                pub fn build_val(self, conn: &::rusqlite::Connection) -> ::core::result::Result<#original_name, ::typed_db::Error> {
+                    let rowid = self.build(&conn)?;
+                    let sql = format!("SELECT * FROM {} WHERE ROWID = {rowid}", #original_name::TABLE_NAME);
+                    let mut stmt = conn.prepare(&sql)?;
+                    Ok(stmt.query_map([], |row| #original_name::try_from(row))?
+                        .next()
+                        .unwrap()?)
+                }
+            };
+            (build_fns, quote! { i64 })
+        };
+        let build_async_fns = Self::impl_build_async(&build_ty, original_name);
+        let build_raw = if self.has_hooks() {
+            quote! { self.insert_hooked(conn).map(|_| 1) }
+        } else {
+            quote! {
+                self.validate()?;
+                let (insert_str, values) = self.insert_sql();
+                let values_refs: Vec<&dyn rusqlite::ToSql> =
+                    values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
+                conn.execute(&insert_str, values_refs.as_slice())
+                    .map_err(::typed_db::Error::for_table::<#original_name>)
+            }
+        };
+
+        let set_states = required.iter().map(|_| quote! { FieldSet });
+        let validated = match self.validated_fields() {
+            Ok(validated) => validated,
+            Err(e) => return e.to_compile_error(),
+        };
+        let validate_args = validated.iter().map(|(f, _)| {
+            let name = &f.name;
+            if f.is_generated() {
+                quote! { None }
+            } else {
+                quote! { self.#name.as_ref() }
+            }
+        });
+
         quote! {
             #[automatically_derived]
             #[derive(Debug, Clone)]
-            pub struct #name {
+            /// Builder inserting a new row. `build*` only exists once every NOT NULL field without a
+            /// default has been set, which the type parameters track.
+            pub struct #name<#(#state_params = FieldUnset),*> {
                 #(#full_types)*
+                _state: ::std::marker::PhantomData<(#(#state_params,)*)>,
             }
 
             #[automatically_derived]
-            impl #name {
+            impl<#(#state_params),*> #name<#(#state_params),*> {
                 #(#with_fns)*
 
                 #[automatically_derived]
-                /// Inserts the item into the db without returning the row id. Returns the default `rusqlite` instead
-                pub fn build_raw(self, conn: &::rusqlite::Connection) -> ::rusqlite::Result<usize> {
+                /// Checks the `#[validate(...)]` rules of the fields that have been set
+                pub fn validate(&self) -> ::core::result::Result<(), ValidationErrors> {
+                    #original_name::validate_fields(#(#validate_args),*)
+                }
+
+                #[automatically_derived]
+                /// The insert statement for the fields that have been set, along with their values.
+                fn insert_sql(self) -> (String, Vec<Box<dyn rusqlite::ToSql>>) {
                     let mut fnames = vec![];
                     let mut values: Vec<Box<dyn rusqlite::ToSql>> = vec![];
 
                     #(#build_str)*
 
+                    if fnames.is_empty() {
+                        let insert_str = format!("INSERT INTO {} DEFAULT VALUES", #original_name::TABLE_NAME);
+                        return (insert_str, values);
+                    }
                     let value_params: Vec<_> = (1..values.len() + 1).map(|i| format!("?{i}")).collect();
                     let insert_str = format!(
                         "INSERT INTO {} ({}) VALUES ({})",
@@ -333,33 +1321,177 @@ impl TableInfo {
                         fnames.join(","),
                         value_params.join(",")
                     );
+                    (insert_str, values)
+                }
+            }
+
+            #[automatically_derived]
+            impl #name<#(#set_states),*> {
+
+                #[automatically_derived]
+                /// The query plan of the insert, without inserting anything
+                pub fn explain(self, conn: &::rusqlite::Connection) -> ::core::result::Result<QueryPlan, ::typed_db::Error> {
+                    let (insert_str, values) = self.insert_sql();
                     let values_refs: Vec<&dyn rusqlite::ToSql> =
                         values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
-                    conn.execute(&insert_str, values_refs.as_slice())
+                    QueryPlan::explain(conn, &insert_str, values_refs.as_slice())
                 }
 
                 #[automatically_derived]
-                /// Inserts the row into the database and returns the [ROWID](https://www.sqlite.org/lang_createtable.html#rowid)
-                pub fn build(self, conn: &::rusqlite::Connection) -> ::rusqlite::Result<i64> {
-                    self.build_raw(&conn)?;
-                    Ok(conn.last_insert_rowid())
+                /// Inserts the item into the db without returning the row id. Returns the default `rusqlite` instead
+                pub fn build_raw(self, conn: &::rusqlite::Connection) -> ::core::result::Result<usize, ::typed_db::Error> {
+                    #build_raw
                 }
 
+                #build_fns
+                #build_async_fns
+            }
+
+        }
+    }
+
+    /// With the `async` feature, `build*` taking an `AsyncConnection`.
+    fn impl_build_async(
+        build_ty: &proc_macro2::TokenStream,
+        original_name: &syn::Ident,
+    ) -> proc_macro2::TokenStream {
+        if !cfg!(feature = "async") {
+            return quote! {};
+        }
+        quote! {
+            #[automatically_derived]
+            /// Async `build`, run on the connection's thread
+            pub async fn build_async(self, conn: &AsyncConnection) -> ::core::result::Result<#build_ty, ::typed_db::Error> {
+                conn.call(move |conn| self.build(conn)).await
+            }
+
+            #[automatically_derived]
+            /// Async `build_raw`, run on the connection's thread
+            pub async fn build_raw_async(self, conn: &AsyncConnection) -> ::core::result::Result<usize, ::typed_db::Error> {
+                conn.call(move |conn| self.build_raw(conn)).await
+            }
+
+            #[automatically_derived]
+            /// Async `build_val`, run on the connection's thread
+            pub async fn build_val_async(self, conn: &AsyncConnection) -> ::core::result::Result<#original_name, ::typed_db::Error> {
+                conn.call(move |conn| self.build_val(conn)).await
+            }
+        }
+    }
+
+    /// `build` and `build_val` for `WITHOUT ROWID` tables, which read the inserted row back with
+    /// `RETURNING` as there is no [ROWID](https://www.sqlite.org/withoutrowid.html) to look it up by.
+    fn impl_build_returning(&self) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let original_name = &self.name;
+        let key_fields = self.key_fields();
+        let key_cols = key_fields
+            .iter()
+            .map(|f| f.name.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        let key_tys = key_fields.iter().map(|f| &f.ty);
+        let key_values = key_fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| f.row_value(i).unwrap_or_else(|e| e.to_compile_error()));
+        let (key_ty, key_value) = if key_fields.len() == 1 {
+            (quote! { #(#key_tys)* }, quote! { #(#key_values)* })
+        } else {
+            (quote! { (#(#key_tys),*) }, quote! { (#(#key_values),*) })
+        };
+
+        let build_fns = quote! {
+            #[automatically_derived]
+            /// Inserts the row into the database and returns its primary key
+            pub fn build(self, conn: &::rusqlite::Connection) -> ::core::result::Result<#key_ty, ::typed_db::Error> {
+                self.validate()?;
+                let (insert_str, values) = self.insert_sql();
+                let sql = format!("{insert_str} RETURNING {}", #key_cols);
+                let values_refs: Vec<&dyn rusqlite::ToSql> =
+                    values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
+                conn.query_row(&sql, values_refs.as_slice(), |row| Ok(#key_value))
+                    .map_err(::typed_db::Error::for_table::<#original_name>)
+            }
+
+            #[automatically_derived]
+            /// Inserts and returns the new object with all data from the db
+            pub fn build_val(self, conn: &::rusqlite::Connection) -> ::core::result::Result<#original_name, ::typed_db::Error> {
+                self.validate()?;
+                let (insert_str, values) = self.insert_sql();
+                let sql = format!("{insert_str} RETURNING {}", #original_name::column_getters());
+                let values_refs: Vec<&dyn rusqlite::ToSql> =
+                    values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
+                conn.query_row(&sql, values_refs.as_slice(), |row| #original_name::try_from(row))
+                    .map_err(::typed_db::Error::for_table::<#original_name>)
+            }
+        };
+        (build_fns, key_ty)
+    }
+
+    /// `build` and `build_val` of `#[hooks]` tables, which read the whole row back with
+    /// `RETURNING` to pass it to `after_insert`.
+    fn impl_build_hooked(
+        &self,
+        without_rowid: bool,
+    ) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+        let original_name = &self.name;
+        let (build, key_ty) = if without_rowid {
+            let key_fields = self.key_fields();
+            let key_names = key_fields.iter().map(|f| &f.name);
+            let key_tys = key_fields.iter().map(|f| &f.ty);
+            let (key_ty, key_value) = if key_fields.len() == 1 {
+                (quote! { #(#key_tys)* }, quote! { #(row.#key_names)* })
+            } else {
+                (quote! { (#(#key_tys),*) }, quote! { (#(row.#key_names),*) })
+            };
+            let build = quote! {
                 #[automatically_derived]
-                /// Inserts and returns the new object with all data from the db
-                pub fn build_val(self, conn: &::rusqlite::Connection) -> ::rusqlite::Result<#original_name> {
-                    let rowid = self.build(&conn)?;
-                    let sql = format!("SELECT * FROM {} WHERE ROWID = {rowid}", #original_name::TABLE_NAME);
-                    let mut stmt = conn.prepare(&sql)?;
-                    stmt.query_map([], |row| {
-                            Ok(#original_name::try_from(row)?)
-                        })?
-                        .next()
-                        .unwrap()
+                /// Inserts the row into the database and returns its primary key
+                pub fn build(self, conn: &::rusqlite::Connection) -> ::core::result::Result<#key_ty, ::typed_db::Error> {
+                    let (_, row) = self.insert_hooked(conn)?;
+                    Ok(#key_value)
                 }
+            };
+            (build, key_ty)
+        } else {
+            let build = quote! {
+                #[automatically_derived]
+                /// Inserts the row into the database and returns the [ROWID](https://www.sqlite.org/lang_createtable.html#rowid)
+                pub fn build(self, conn: &::rusqlite::Connection) -> ::core::result::Result<i64, ::typed_db::Error> {
+                    Ok(self.insert_hooked(conn)?.0)
+                }
+            };
+            (build, quote! { i64 })
+        };
+
+        let build_fns = quote! {
+            #[automatically_derived]
+            /// Runs the insert hooks around inserting the row. Returns the ROWID, which is taken
+            /// before `after_insert` can insert anything else, and the inserted row.
+            fn insert_hooked(mut self, conn: &::rusqlite::Connection) -> ::core::result::Result<(i64, #original_name), ::typed_db::Error> {
+                <#original_name as DbTableHooks>::before_insert(&mut self, conn)?;
+                self.validate()?;
+                let (insert_str, values) = self.insert_sql();
+                let sql = format!("{insert_str} RETURNING {}", #original_name::column_getters());
+                let values_refs: Vec<&dyn rusqlite::ToSql> =
+                    values.iter().map(|v| v as &dyn rusqlite::ToSql).collect();
+                let row = conn
+                    .query_row(&sql, values_refs.as_slice(), |row| #original_name::try_from(row))
+                    .map_err(::typed_db::Error::for_table::<#original_name>)?;
+                let rowid = conn.last_insert_rowid();
+                <#original_name as DbTableHooks>::after_insert(&row, conn)?;
+                Ok((rowid, row))
             }
 
-        }
+            #build
+
+            #[automatically_derived]
+            /// Inserts and returns the new object with all data from the db
+            pub fn build_val(self, conn: &::rusqlite::Connection) -> ::core::result::Result<#original_name, ::typed_db::Error> {
+                Ok(self.insert_hooked(conn)?.1)
+            }
+        };
+        (build_fns, key_ty)
     }
 
     fn impl_select_where(&self) -> proc_macro2::TokenStream {
@@ -367,12 +1499,10 @@ impl TableInfo {
 
         quote! {
             #[automatically_derived]
-            fn select(conn: &rusqlite::Connection, where_clause: &str, params: impl rusqlite::Params) -> rusqlite::Result<Box<[Self]>> {
-                let sql = format!("SELECT {} FROM {} {}", #comma_separated_cols, Self::TABLE_NAME, where_clause);
+            fn select(conn: &rusqlite::Connection, where_clause: &str, params: impl rusqlite::Params) -> ::core::result::Result<Box<[Self]>, ::typed_db::Error> {
+                let sql = format!("SELECT {} FROM {} {}", #comma_separated_cols, Self::SELECT_FROM, where_clause);
                 let mut stmt = conn.prepare(&sql)?;
-                let iter = stmt.query_map(params, |row| {
-                    Ok(Self::try_from(row)?)
-                })?
+                let iter = stmt.query_map(params, |row| Self::try_from(row))?
                 .collect::<rusqlite::Result<_>>()?;
                 Ok(iter)
             }
@@ -382,7 +1512,8 @@ impl TableInfo {
     fn impl_try_from_row(&self) -> proc_macro2::TokenStream {
         let row_getters = self.fields.iter().enumerate().map(|(i, f)| {
             let name = &f.name;
-            quote! { #name: row.get(#i)?, }
+            let value = f.row_value(i).unwrap_or_else(|e| e.to_compile_error());
+            quote! { #name: #value, }
         });
         let name = &self.name;
 
@@ -402,10 +1533,12 @@ impl TableInfo {
     fn impl_table_info_str(&self) -> proc_macro2::TokenStream {
         let name = &self.name;
         let builder_name = self.builder_name();
-        let fields = self.fields.iter().map(|f| {
+        let fields = self.insertable_fields().map(|f| {
             let field_name = &f.name;
             quote! {#field_name: None,}
         });
+        let validate = self.impl_validate();
+        let update = self.impl_update();
 
         quote! {
             #[automatically_derived]
@@ -413,8 +1546,320 @@ impl TableInfo {
                 pub fn new() -> #builder_name {
                     #builder_name {
                         #(#fields)*
+                        _state: ::std::marker::PhantomData,
                     }
                 }
+
+                #validate
+                #update
+            }
+        }
+    }
+
+    /// The fields with `#[validate(...)]` rules, along with the statements checking them.
+    fn validated_fields(&self) -> Result<Vec<(&TableFieldInfo, proc_macro2::TokenStream)>> {
+        let mut fields = Vec::new();
+        for f in self.fields.iter() {
+            if let Some(validation) = f.validation()? {
+                fields.push((f, validation));
+            }
+        }
+        Ok(fields)
+    }
+
+    /// `validate`, along with `validate_fields` which the builder's `validate` shares.
+    fn impl_validate(&self) -> proc_macro2::TokenStream {
+        let validated = match self.validated_fields() {
+            Ok(validated) => validated,
+            Err(e) => return e.to_compile_error(),
+        };
+        let params = validated.iter().map(|(f, _)| {
+            let name = &f.name;
+            let ty = &f.ty;
+            quote! { #name: ::std::option::Option<&#ty> }
+        });
+        let names = validated.iter().map(|(f, _)| &f.name);
+        let body = if validated.is_empty() {
+            quote! { Ok(()) }
+        } else {
+            let checks = validated.iter().map(|(_, check)| check);
+            quote! {
+                let mut __errors = ValidationErrors::new(<Self as DbTable>::TABLE_NAME);
+                #(#checks)*
+                __errors.into_result()
+            }
+        };
+
+        quote! {
+            #[automatically_derived]
+            #[allow(clippy::too_many_arguments)]
+            /// Checks the `#[validate(...)]` rules of the fields that are `Some`
+            fn validate_fields(#(#params),*) -> ::core::result::Result<(), ValidationErrors> {
+                #body
+            }
+
+            #[automatically_derived]
+            /// Checks the `#[validate(...)]` rules of every field
+            pub fn validate(&self) -> ::core::result::Result<(), ValidationErrors> {
+                Self::validate_fields(#(Some(&self.#names)),*)
+            }
+        }
+    }
+
+    /// `update`, for tables with a key and other fields to write.
+    fn impl_update(&self) -> proc_macro2::TokenStream {
+        let key_fields = self.key_fields();
+        let set_fields = self
+            .insertable_fields()
+            .filter(|f| !f.is_primary_key() && !f.is_composite_key())
+            .collect::<Vec<_>>();
+        if key_fields.is_empty() || set_fields.is_empty() {
+            return quote! {};
+        }
+        let assignments = set_fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("{} = ?{}", f.name, i + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let conditions = key_fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("{} = ?{}", f.name, set_fields.len() + i + 1))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let self_ident = syn::Ident::new("self", proc_macro2::Span::call_site());
+        let mut values = set_fields
+            .iter()
+            .chain(key_fields.iter())
+            .map(|f| f.bind_ref(&self_ident))
+            .collect::<Vec<_>>();
+        let version = match self.version_field() {
+            Ok(version) => version,
+            Err(e) => return e.to_compile_error(),
+        };
+        let mut touched = Vec::new();
+        for f in set_fields.iter() {
+            match f.timestamp() {
+                Ok(Some(TimestampAttr::UpdatedAt { .. })) => touched.push((&f.name, &f.ty)),
+                Ok(_) => {}
+                Err(e) => return e.to_compile_error(),
+            }
+        }
+        let touched_names = touched.iter().map(|(fname, _)| *fname).collect::<Vec<_>>();
+        let previous = touched_names
+            .iter()
+            .map(|fname| syn::Ident::new(&format!("__previous_{fname}"), fname.span()))
+            .collect::<Vec<_>>();
+        let touched_tys = touched.iter().map(|(_, ty)| *ty);
+        let touch = quote! {
+            #(let #previous = ::core::mem::replace(&mut self.#touched_names, <#touched_tys as Timestamp>::now());)*
+        };
+        let (sql, check_current, bump_version, check_version) = match version {
+            Some(version) => {
+                let vname = &version.name;
+                values.push(quote! { &__read_version as &dyn rusqlite::ToSql });
+                let sql = format!(
+                    "UPDATE {} SET {assignments} WHERE {conditions} AND {vname} = ?{}",
+                    self.name,
+                    values.len()
+                );
+                let stale = quote! {
+                    return Err(::typed_db::Error::StaleVersion {
+                        table: <Self as DbTable>::TABLE_NAME.to_string(),
+                    });
+                };
+                // `before_update` edits can't be undone, so the version is checked before it
+                let check_current = if self.has_hooks() {
+                    let key_conditions = key_fields
+                        .iter()
+                        .enumerate()
+                        .map(|(i, f)| format!("{} = ?{}", f.name, i + 1))
+                        .collect::<Vec<_>>()
+                        .join(" AND ");
+                    let current_sql = format!(
+                        "SELECT EXISTS (SELECT 1 FROM {} WHERE {key_conditions} AND {vname} = ?{})",
+                        self.name,
+                        key_fields.len() + 1
+                    );
+                    let key_values = key_fields.iter().map(|f| f.bind_ref(&self_ident));
+                    quote! {
+                        let current: bool = conn
+                            .query_row(
+                                #current_sql,
+                                [#(#key_values,)* &self.#vname as &dyn rusqlite::ToSql].as_slice(),
+                                |row| row.get(0),
+                            )
+                            .map_err(::typed_db::Error::for_table::<Self>)?;
+                        if !current {
+                            #stale
+                        }
+                    }
+                } else {
+                    quote! {}
+                };
+                let bump = quote! {
+                    let __read_version = self.#vname;
+                    self.#vname += 1;
+                };
+                let check = quote! {
+                    if !matches!(updated, Ok(1..)) {
+                        self.#vname = __read_version;
+                        #(self.#touched_names = #previous;)*
+                    }
+                    if let Ok(0) = updated {
+                        #stale
+                    }
+                };
+                (sql, check_current, bump, check)
+            }
+            None => (
+                format!("UPDATE {} SET {assignments} WHERE {conditions}", self.name),
+                quote! {},
+                quote! {},
+                quote! {},
+            ),
+        };
+        let (before_update, after_update) = if self.has_hooks() {
+            (
+                quote! { <Self as DbTableHooks>::before_update(self, conn)?; },
+                quote! { <Self as DbTableHooks>::after_update(self, conn)?; },
+            )
+        } else {
+            (quote! {}, quote! {})
+        };
+        // The `#[updated_at(trigger)]` triggers skip rows written while `<Table>Updating` has one
+        let (hold_triggers, release_triggers) = match self.has_updated_at_trigger() {
+            Ok(true) => {
+                let hold = format!(
+                    "SAVEPOINT typed_db_update; INSERT INTO {}Updating VALUES (1)",
+                    self.name
+                );
+                let release = format!("DELETE FROM {}Updating; RELEASE typed_db_update", self.name);
+                (
+                    quote! { conn.execute_batch(#hold)?; },
+                    quote! { conn.execute_batch(#release)?; },
+                )
+            }
+            Ok(false) => (quote! {}, quote! {}),
+            Err(e) => return e.to_compile_error(),
+        };
+        // Only hooks, `#[updated_at]` fields and the version change `self`
+        let receiver = if self.has_hooks() || !touched_names.is_empty() || version.is_some() {
+            quote! { &mut self }
+        } else {
+            quote! { &self }
+        };
+
+        quote! {
+            #[automatically_derived]
+            /// Writes every field to the row with the same primary key, after setting the
+            /// `#[updated_at]` fields and checking the `#[validate(...)]` rules. Returns the
+            /// number of updated rows.
+            ///
+            /// With a `#[version]` field the row is only written if its version is still the one
+            /// read, failing with `StaleVersion` otherwise, and the version is incremented. A stale
+            /// update leaves `self` as it was and doesn't run `before_update`.
+            pub fn update(#receiver, conn: &::rusqlite::Connection) -> ::core::result::Result<usize, ::typed_db::Error> {
+                #check_current
+                #before_update
+                #touch
+                self.validate()?;
+                #bump_version
+                #hold_triggers
+                let updated = conn
+                    .execute(#sql, [#(#values),*].as_slice())
+                    .map_err(::typed_db::Error::for_table::<Self>);
+                #release_triggers
+                #check_version
+                let updated = updated?;
+                #after_update
+                Ok(updated)
+            }
+        }
+    }
+
+    /// `DbTable::delete` for `#[hooks]` tables, deleting the matching rows one at a time by key
+    /// so each gets its `before_delete` and `after_delete`. `#[soft_delete]` rows are marked
+    /// deleted instead. The rows are deleted within a savepoint, so an error from a
+    /// `before_delete` leaves them all in place, and `after_delete` only runs once it's released.
+    fn impl_delete_hooked(&self) -> proc_macro2::TokenStream {
+        let soft_delete = match self.soft_delete_field() {
+            Ok(soft_delete) => soft_delete,
+            Err(e) => return e.to_compile_error(),
+        };
+        let key_fields = self.key_fields();
+        if key_fields.is_empty() {
+            return syn::Error::new(
+                self.name.span(),
+                "`#[hooks]` requires a `#[primary_key]` or `#[composite_key]`",
+            )
+            .to_compile_error();
+        }
+        let offset = usize::from(soft_delete.is_some());
+        let conditions = key_fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("{} = ?{}", f.name, offset + i + 1))
+            .collect::<Vec<_>>()
+            .join(" AND ");
+        let row_ident = syn::Ident::new("row", proc_macro2::Span::call_site());
+        let mut values = key_fields
+            .iter()
+            .map(|f| f.bind_ref(&row_ident))
+            .collect::<Vec<_>>();
+        let sql = match soft_delete {
+            Some(field) => {
+                let ty = field.option_inner();
+                values.insert(
+                    0,
+                    quote! { &<#ty as Timestamp>::now() as &dyn rusqlite::ToSql },
+                );
+                format!(
+                    "UPDATE {} SET {} = ?1 WHERE {conditions}",
+                    self.name, field.name
+                )
+            }
+            None => format!("DELETE FROM {} WHERE {conditions}", self.name),
+        };
+
+        quote! {
+            #[automatically_derived]
+            fn delete(
+                conn: &rusqlite::Connection,
+                where_clause: &str,
+                params: impl rusqlite::Params,
+            ) -> ::core::result::Result<usize, ::typed_db::Error> {
+                conn.execute_batch("SAVEPOINT typed_db_delete")?;
+                let deleted = (|| {
+                    let rows = Self::select(conn, where_clause, params)?;
+                    let mut deleted = 0;
+                    for row in rows.iter() {
+                        <Self as DbTableHooks>::before_delete(row, conn)?;
+                        deleted += conn
+                            .execute(#sql, [#(#values),*].as_slice())
+                            .map_err(::typed_db::Error::for_table::<Self>)?;
+                    }
+                    ::core::result::Result::<_, ::typed_db::Error>::Ok((rows, deleted))
+                })();
+                if deleted.is_err() {
+                    conn.execute_batch("ROLLBACK TO typed_db_delete")?;
+                }
+                conn.execute_batch("RELEASE typed_db_delete")?;
+                let (rows, deleted) = deleted?;
+                for row in rows.iter() {
+                    <Self as DbTableHooks>::after_delete(row, conn)?;
+                }
+                Ok(deleted)
+            }
+
+            #[automatically_derived]
+            fn explain_delete(
+                conn: &rusqlite::Connection,
+                where_clause: &str,
+                params: impl rusqlite::Params,
+            ) -> ::core::result::Result<QueryPlan, ::typed_db::Error> {
+                Self::explain_select(conn, where_clause, params)
             }
         }
     }
@@ -439,13 +1884,22 @@ impl TableInfo {
             Err(e) => e.into_compile_error(),
         });
 
-        let build_fields = self.fields.iter().map(|f| {
+        let build_fields = self.insertable_fields().map(|f| {
             let field_name = &f.name;
             let with_name = syn::Ident::new(&format!("with_{field_name}"), field_name.span());
             let ty = &f.ty;
 
             quote! {.#with_name(<#ty as ::std::default::Default>::default())}
         });
+        // Default values needn't pass the `#[validate(...)]` rules, so only the insert is skipped
+        // for validated tables, and its statement is still prepared against the created table
+        let build = match self.validated_fields() {
+            Ok(validated) if validated.is_empty() => {
+                quote! { #name::new()#(#build_fields)*.build_val(&conn)?; }
+            }
+            Ok(_) => quote! { #name::new()#(#build_fields)*.explain(&conn)?; },
+            Err(e) => e.to_compile_error(),
+        };
 
         quote! {
             #[cfg(test)]
@@ -462,7 +1916,7 @@ impl TableInfo {
                     #name::create_table(&conn)?;
                     conn.execute("PRAGMA foreign_keys = OFF;", [])?;
 
-                    #name::new()#(#build_fields)*.build_val(&conn)?;
+                    #build
                     Ok(())
                 }
             }
@@ -0,0 +1,71 @@
+use syn::{
+    Result, Token,
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+};
+
+mod kw {
+    syn::custom_keyword!(strict);
+    syn::custom_keyword!(without_rowid);
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TableOption {
+    Strict,
+    WithoutRowid,
+}
+
+impl Parse for TableOption {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::strict) {
+            input.parse::<kw::strict>()?;
+            Ok(TableOption::Strict)
+        } else if lookahead.peek(kw::without_rowid) {
+            input.parse::<kw::without_rowid>()?;
+            Ok(TableOption::WithoutRowid)
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
+/// Options from `#[table(...)]` attributes on the struct.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableOptions {
+    pub strict: bool,
+    pub without_rowid: bool,
+}
+
+impl TableOptions {
+    pub fn from_attributes(attrs: &[syn::Attribute]) -> Result<Self> {
+        let mut options = Self::default();
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("table")) {
+            let parsed =
+                attr.parse_args_with(Punctuated::<TableOption, Token![,]>::parse_terminated)?;
+            for option in parsed {
+                match option {
+                    TableOption::Strict => options.strict = true,
+                    TableOption::WithoutRowid => options.without_rowid = true,
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// The table options clause following the column definitions.
+    pub fn suffix(&self) -> String {
+        let mut options = Vec::new();
+        if self.strict {
+            options.push("STRICT");
+        }
+        if self.without_rowid {
+            options.push("WITHOUT ROWID");
+        }
+        if options.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", options.join(", "))
+        }
+    }
+}
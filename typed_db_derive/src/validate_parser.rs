@@ -0,0 +1,269 @@
+use quote::{ToTokens, quote};
+use syn::{
+    LitStr, Result, Token, parenthesized,
+    parse::{Parse, ParseStream},
+};
+
+mod kw {
+    syn::custom_keyword!(length);
+    syn::custom_keyword!(min);
+    syn::custom_keyword!(max);
+    syn::custom_keyword!(email);
+    syn::custom_keyword!(range);
+    syn::custom_keyword!(regex);
+    syn::custom_keyword!(with);
+    syn::custom_keyword!(check);
+}
+
+/// A number in a `range(..)`, with an optional minus sign.
+#[derive(Debug, Clone)]
+pub struct Bound {
+    negative: bool,
+    lit: syn::Lit,
+}
+
+impl Parse for Bound {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let negative = input.parse::<Option<Token![-]>>()?.is_some();
+        let lit: syn::Lit = input.parse()?;
+        match lit {
+            syn::Lit::Int(_) | syn::Lit::Float(_) => Ok(Self { negative, lit }),
+            _ => Err(syn::Error::new(lit.span(), "Expected a number")),
+        }
+    }
+}
+
+impl Bound {
+    fn sql(&self) -> String {
+        let digits = match &self.lit {
+            syn::Lit::Int(lit) => lit.base10_digits(),
+            syn::Lit::Float(lit) => lit.base10_digits(),
+            _ => unreachable!("only numbers are parsed"),
+        };
+        let sign = if self.negative { "-" } else { "" };
+        format!("{sign}{digits}")
+    }
+}
+
+impl ToTokens for Bound {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        if self.negative {
+            tokens.extend(quote! { - });
+        }
+        self.lit.to_tokens(tokens);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ValidateRule {
+    /// `length(min = 1, max = 255)`, either bound may be left out
+    Length {
+        min: Option<syn::LitInt>,
+        max: Option<syn::LitInt>,
+    },
+    Email,
+    /// `range(0..=120)`, `range(0..)` or `range(..10)`
+    Range {
+        start: Option<Bound>,
+        end: Option<Bound>,
+        inclusive: bool,
+    },
+    /// `regex = "^[a-z]+$"`
+    Regex(LitStr),
+    /// `with = path`, a function called with a reference to the value, returning
+    /// `Result<(), impl Display>`
+    With(syn::Path),
+}
+
+impl ValidateRule {
+    /// The rule as an SQL expression over `column`, for the ones that have one.
+    pub fn check_sql(&self, column: &str) -> Option<String> {
+        let out = match self {
+            ValidateRule::Length { min, max } => {
+                let len = format!("length({column})");
+                let min = min
+                    .as_ref()
+                    .map(|min| format!("{len} >= {}", min.base10_digits()));
+                let max = max
+                    .as_ref()
+                    .map(|max| format!("{len} <= {}", max.base10_digits()));
+                [min, max]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
+            }
+            ValidateRule::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let op = if *inclusive { "<=" } else { "<" };
+                let start = start.as_ref().map(|s| format!("{column} >= {}", s.sql()));
+                let end = end.as_ref().map(|e| format!("{column} {op} {}", e.sql()));
+                [start, end]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
+            }
+            _ => return None,
+        };
+        Some(out)
+    }
+
+    /// Statements pushing to `__errors` when `value`, a reference to the field, breaks the rule.
+    pub fn to_validation(&self, field: &str) -> proc_macro2::TokenStream {
+        match self {
+            ValidateRule::Length { min, max } => {
+                let min = option_tokens(min);
+                let max = option_tokens(max);
+                quote! { __errors.length(#field, value, #min, #max); }
+            }
+            ValidateRule::Email => {
+                quote! { __errors.email(#field, AsRef::<str>::as_ref(value)); }
+            }
+            ValidateRule::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let range = if *inclusive {
+                    quote! { #start..=#end }
+                } else {
+                    quote! { #start..#end }
+                };
+                quote! { __errors.range(#field, value, #range); }
+            }
+            ValidateRule::Regex(pattern) => quote! {
+                static REGEX: LazyRegex = LazyRegex::new(#pattern);
+                __errors.regex(#field, AsRef::<str>::as_ref(value), &REGEX);
+            },
+            ValidateRule::With(path) => quote! { __errors.with(#field, #path(value)); },
+        }
+    }
+}
+
+fn option_tokens(value: &Option<syn::LitInt>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote! { Some(#value) },
+        None => quote! { None },
+    }
+}
+
+/// `#[validate(rule, ...)]`, where `check` also adds the `length` and `range` rules as `CHECK`
+/// constraints.
+#[derive(Debug, Clone)]
+pub struct ValidateAttr {
+    pub rules: Vec<ValidateRule>,
+    pub check: bool,
+}
+
+impl Parse for ValidateAttr {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let mut rules = Vec::new();
+        let mut check = false;
+        loop {
+            let lookahead = input.lookahead1();
+            if lookahead.peek(kw::length) {
+                input.parse::<kw::length>()?;
+                rules.push(parse_length(input)?);
+            } else if lookahead.peek(kw::email) {
+                input.parse::<kw::email>()?;
+                rules.push(ValidateRule::Email);
+            } else if lookahead.peek(kw::range) {
+                input.parse::<kw::range>()?;
+                rules.push(parse_range(input)?);
+            } else if lookahead.peek(kw::regex) {
+                input.parse::<kw::regex>()?;
+                input.parse::<Token![=]>()?;
+                let pattern: LitStr = input.parse()?;
+                if let Err(err) = regex_syntax::Parser::new().parse(&pattern.value()) {
+                    return Err(syn::Error::new(
+                        pattern.span(),
+                        format!("invalid regex: {err}"),
+                    ));
+                }
+                rules.push(ValidateRule::Regex(pattern));
+            } else if lookahead.peek(kw::with) {
+                input.parse::<kw::with>()?;
+                input.parse::<Token![=]>()?;
+                rules.push(ValidateRule::With(input.parse()?));
+            } else if lookahead.peek(kw::check) {
+                input.parse::<kw::check>()?;
+                check = true;
+            } else {
+                return Err(lookahead.error());
+            }
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+        }
+        Ok(Self { rules, check })
+    }
+}
+
+fn parse_length(input: ParseStream) -> Result<ValidateRule> {
+    let content;
+    let parens = parenthesized!(content in input);
+    let mut min = None;
+    let mut max = None;
+    while !content.is_empty() {
+        let lookahead = content.lookahead1();
+        if lookahead.peek(kw::min) {
+            content.parse::<kw::min>()?;
+            content.parse::<Token![=]>()?;
+            min = Some(content.parse()?);
+        } else if lookahead.peek(kw::max) {
+            content.parse::<kw::max>()?;
+            content.parse::<Token![=]>()?;
+            max = Some(content.parse()?);
+        } else {
+            return Err(lookahead.error());
+        }
+        if !content.is_empty() {
+            content.parse::<Token![,]>()?;
+        }
+    }
+    if min.is_none() && max.is_none() {
+        return Err(syn::Error::new(
+            parens.span.join(),
+            "Expected `length(min = .., max = ..)`",
+        ));
+    }
+    Ok(ValidateRule::Length { min, max })
+}
+
+fn parse_range(input: ParseStream) -> Result<ValidateRule> {
+    let content;
+    parenthesized!(content in input);
+    let start = if content.peek(Token![..=]) || content.peek(Token![..]) {
+        None
+    } else {
+        Some(content.parse()?)
+    };
+    let inclusive = if content.peek(Token![..=]) {
+        content.parse::<Token![..=]>()?;
+        true
+    } else {
+        content.parse::<Token![..]>()?;
+        false
+    };
+    let end = if content.is_empty() {
+        None
+    } else {
+        Some(content.parse()?)
+    };
+    if inclusive && end.is_none() {
+        return Err(content.error("Expected the end of the range"));
+    }
+    Ok(ValidateRule::Range {
+        start,
+        end,
+        inclusive,
+    })
+}